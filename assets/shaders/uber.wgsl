@@ -27,12 +27,106 @@
 // @group(2) @binding(100)
 // var<uniform> my_extended_material: MyExtendedMaterial;
 
-@group(2) @binding(100) var<storage, read> sdfs: array<vec4<f32>>;
+// matches `GpuColorReveal`
+struct ColorReveal {
+    position: vec3<f32>,
+    radius: f32,
+    color: u32,
+    falloff: u32,
+    falloff_width: f32,
+    strength: f32,
+}
+
+@group(2) @binding(100) var<storage, read> color_reveals: array<ColorReveal>;
 @group(2) @binding(101) var decals_color_texture: texture_2d<f32>;
 @group(2) @binding(102) var decals_color_sampler: sampler;
 @group(2) @binding(103) var grit_color_texture: texture_2d<f32>;
 @group(2) @binding(104) var grit_color_sampler: sampler;
 
+// matches `GpuRevealGrid`, each cell is a (start, count) range of `reveal_indices`
+struct RevealGrid {
+    origin: vec2<f32>,
+    cell_size: f32,
+    columns: u32,
+    rows: u32,
+    cells: array<vec2<u32>>,
+}
+
+@group(2) @binding(105) var<storage, read> reveal_grid: RevealGrid;
+@group(2) @binding(106) var<storage, read> reveal_indices: array<u32>;
+
+// matches `GpuPaintBounds`, a zero extent means there's no paint
+struct PaintBounds {
+    origin: vec3<f32>,
+    extent: vec3<f32>,
+}
+
+@group(2) @binding(107) var<storage, read> paint_bounds: PaintBounds;
+@group(2) @binding(108) var paint_texture: texture_3d<f32>;
+@group(2) @binding(109) var paint_sampler: sampler;
+
+// matches `GpuDecal`
+struct Decal {
+    world_to_decal: mat4x4<f32>,
+    uv_rect: vec4<f32>,
+    blend: u32,
+    opacity: f32,
+}
+
+@group(2) @binding(110) var<storage, read> decals: array<Decal>;
+
+// `color` with every decal whose box holds `position` blended over it
+fn apply_decals(color: vec3<f32>, position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var out = color;
+    for (var i = 0u; i < arrayLength(&decals); i++) {
+        let decal = decals[i];
+        let local = (decal.world_to_decal * vec4(position, 1.)).xyz;
+        if decal.opacity <= 0. || any(abs(local) > vec3(0.5)) {
+            continue;
+        }
+        // the decal's +Z axis in world space, only surfaces facing it are projected onto
+        let forward = normalize(vec3(
+            decal.world_to_decal[0].z,
+            decal.world_to_decal[1].z,
+            decal.world_to_decal[2].z,
+        ));
+        if dot(normal, forward) <= 0. {
+            continue;
+        }
+        let uv = mix(decal.uv_rect.xy, decal.uv_rect.zw, vec2(local.x + 0.5, 0.5 - local.y));
+        let texel = textureSampleLevel(decals_color_texture, decals_color_sampler, uv, 0.);
+        let alpha = texel.a * decal.opacity;
+        switch decal.blend {
+            // multiply
+            case 1u: { out = mix(out, out * texel.rgb, alpha); }
+            // add
+            case 2u: { out = out + texel.rgb * alpha; }
+            // alpha
+            default: { out = mix(out, texel.rgb, alpha); }
+        }
+    }
+    return out;
+}
+
+// how much of a surface's color `reveal` brings back at `position`, from 0 to 1
+fn reveal_amount(reveal: ColorReveal, position: vec3<f32>, grit: f32) -> f32 {
+    // how far through the falloff `position` is, 0 inside the radius
+    let edge = max(length(position - reveal.position) - reveal.radius, 0.)
+        / max(reveal.falloff_width, 0.0001);
+    var amount: f32;
+    switch reveal.falloff {
+        // hard
+        case 0u: { amount = select(0., 1., edge <= 0.); }
+        // linear
+        case 1u: { amount = 1. - saturate(edge); }
+        // smooth
+        case 2u: { amount = 1. - smoothstep(0., 1., edge); }
+        // grit, low grit lets the reveal spread further
+        default: { amount = 1. - saturate(edge * max(grit, 0.1)); }
+    }
+    return amount * saturate(reveal.strength);
+}
+
 @fragment
 fn fragment(
     #ifdef MULTISAMPLED
@@ -50,35 +144,42 @@ fn fragment(
 
     // we can optionally modify the input before lighting and alpha_discard is applied
     // pbr_input.material.base_color.b = pbr_input.material.base_color.r;
-    var distance_to_nearest: f32 = 1000.;
-    for (var i = 0; i < i32(arrayLength(&sdfs)); i++) {
-            distance_to_nearest = min(
-                distance_to_nearest,
-                // - max_distance (3 for main game, 1 for example)
-                length(in.world_position.xyz - sdfs[i].xyz) - 3.0
+    let grit = textureSample(grit_color_texture, grit_color_sampler, in.world_position.xz / 3.).r;
+    var revealed: f32 = 0.;
+    // only the reveals bucketed into this fragment's grid cell can reach it
+    let cell = vec2<i32>(floor((in.world_position.xz - reveal_grid.origin) / reveal_grid.cell_size));
+    if all(cell >= vec2(0)) && cell.x < i32(reveal_grid.columns) && cell.y < i32(reveal_grid.rows) {
+        let range = reveal_grid.cells[u32(cell.y) * reveal_grid.columns + u32(cell.x)];
+        for (var i = range.x; i < range.x + range.y; i++) {
+            revealed = max(
+                revealed,
+                reveal_amount(color_reveals[reveal_indices[i]], in.world_position.xyz, grit)
             );
+        }
     }
-    if distance_to_nearest <= 10.0 {
-        let can = 3. * distance_to_nearest * textureSample(grit_color_texture, grit_color_sampler, in.world_position.xz / 3.).r;
-        let color = mix(
-            pbr_input.material.base_color,
-            vec4(0.8,0.8,0.8,pbr_input.material.base_color.a),
-            saturate(can)
-        );
-        pbr_input.material.base_color.r = color.r;
-        pbr_input.material.base_color.g = color.g;
-        pbr_input.material.base_color.b = color.b;
-
-    } else {
-        pbr_input.material.base_color.r = 0.8;
-        pbr_input.material.base_color.g = 0.8;
-        pbr_input.material.base_color.b = 0.8;
+    // paint left behind by `PaintReveal`s, one channel per reveal color
+    if all(paint_bounds.extent > vec3(0.)) {
+        let uvw = (in.world_position.xyz - paint_bounds.origin) / paint_bounds.extent;
+        if all(uvw >= vec3(0.)) && all(uvw <= vec3(1.)) {
+            let paint = textureSampleLevel(paint_texture, paint_sampler, uvw, 0.);
+            revealed = max(revealed, max(paint.r, max(paint.g, paint.b)));
+        }
     }
+    let color = mix(
+        vec4(0.8,0.8,0.8,pbr_input.material.base_color.a),
+        pbr_input.material.base_color,
+        revealed
+    );
+    // decals go on after the reveal so hints show on grey surfaces too
+    let decaled = apply_decals(color.rgb, in.world_position.xyz, pbr_input.world_normal);
+    pbr_input.material.base_color.r = decaled.r;
+    pbr_input.material.base_color.g = decaled.g;
+    pbr_input.material.base_color.b = decaled.b;
 
 
     // pbr_input.material.base_color = vec4(distance_to_nearest,distance_to_nearest,distance_to_nearest,1.);
 
-    // pbr_input.material.base_color = vec4(sdfs[0].x,sdfs[0].y,sdfs[0].z,1.);
+    // pbr_input.material.base_color = vec4(color_reveals[0].position,1.);
     // pbr_input.material.base_color = vec4(in.position.x/10.,in.position.y/10.,in.position.z/10.,1.);
 
     // pbr_input.material.base_color = vec4(in.world_position.x,in.world_position.y,in.world_position.z,1.);
@@ -10,7 +10,7 @@ use bevy::{
 };
 use bevy_15_game::{
     materials::{
-        uber::{ColorReveal, UberMaterial},
+        uber::{ColorReveal, RevealColor, UberMaterial},
         MaterialsPlugin,
     },
     post_process::{
@@ -62,22 +62,17 @@ fn setup(
         >,
     >,
     mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+    mut images: ResMut<Assets<Image>>,
     asset_server: Res<AssetServer>,
 ) {
-    // Example data for the storage buffer
-    let sphere_data: Vec<[f32; 4]> = vec![];
-
-    let sdfs =
-        buffers.add(ShaderStorageBuffer::from(sphere_data));
-
-    let uber_handle = UberMaterial {
-        sdfs: sdfs,
-        decals: None,
-        grit: Some(
+    let uber_handle = UberMaterial::new(
+        &mut buffers,
+        &mut images,
+        Some(
             asset_server
                 .load("textures/gritty_texture.png"),
         ),
-    };
+    );
 
     // sphere
     commands.spawn((
@@ -90,7 +85,10 @@ fn setup(
             extension: uber_handle.clone(),
         })),
         Transform::from_xyz(2.0, 0.5, 0.0),
-        ColorReveal::Red,
+        ColorReveal {
+            radius: 1.,
+            ..default()
+        },
         DrawSection,
     ));
 
@@ -188,16 +186,16 @@ fn move_color_reveals(
     mut q: Query<(&mut Transform, &ColorReveal)>,
     time: Res<Time>,
 ) {
-    for (mut t, color) in &mut q {
-        match color {
-            ColorReveal::Red => {
+    for (mut t, reveal) in &mut q {
+        match reveal.color {
+            RevealColor::Red => {
                 t.translation.x =
                     (time.elapsed_secs()).sin() * 2.;
                 t.translation.z =
                     (time.elapsed_secs()).cos() * 2.;
             }
-            ColorReveal::Green => todo!(),
-            ColorReveal::Blue => {
+            RevealColor::Green => todo!(),
+            RevealColor::Blue => {
                 t.translation.x =
                     (time.elapsed_secs()).cos() * 2.;
                 t.translation.z =
@@ -1,63 +1,417 @@
-use avian3d::math::PI;
+use avian3d::{
+    math::PI,
+    prelude::{
+        Collider, LinearVelocity, Sensor, ShapeCastConfig,
+        SpatialQuery, SpatialQueryFilter,
+    },
+};
 use bevy::prelude::*;
-use std::f32::consts::FRAC_PI_2;
+use bevy_tnua::TnuaProximitySensor;
 
-use crate::Player;
+use crate::{
+    Holding, Player, PlayerIndex, free_camera::FreeCamera,
+    platforms::Platform,
+};
+
+pub mod shots;
 
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app
-            // .init_resource::<PlayerCameraSettings>()
-            //     .register_type::<PlayerCameraSettings>()
+        app.init_resource::<PlayerCameraSettings>()
+            .register_type::<PlayerCameraSettings>()
             .register_type::<CameraRig>()
+            .register_type::<SmoothedCameraRig>()
+            .register_type::<CameraCollision>()
+            .register_type::<LevelCameraZoom>()
+            .add_observer(on_add_level_camera_zoom)
+            .add_plugins(shots::CameraShotsPlugin)
+            // The player's Transform is interpolated
+            // between physics ticks, so following it
+            // after Update is smooth where following
+            // it in FixedUpdate would jitter.
             .add_systems(
-                FixedUpdate,
-                control_camera.never_param_warn(),
+                PostUpdate,
+                (
+                    target_camera_to_player
+                        .never_param_warn(),
+                    auto_zoom.never_param_warn(),
+                    control_camera.never_param_warn(),
+                    shots::apply_camera_shot
+                        .never_param_warn(),
+                )
+                    .chain()
+                    .before(
+                        TransformSystem::TransformPropagate,
+                    ),
             );
     }
 }
 
 #[derive(Component)]
-#[require(CameraRig)]
+#[require(
+    CameraRig,
+    SmoothedCameraRig,
+    CameraCollision,
+    shots::CameraShot
+)]
 pub struct PlayerCamera;
 
-// #[derive(Resource, Reflect)]
-// #[reflect(Resource)]
-// struct PlayerCameraSettings {
-//     offset: Vec3,
-//     decay: f32,
-// }
-// impl Default for PlayerCameraSettings {
-//     fn default() -> Self {
-//         Self {
-//             offset: Vec3::new(6.9, 4.1, 6.9),
-//             decay: 4.,
-//         }
-//     }
-// }
+/// How the camera follows the player.
+///
+/// The `*_decay` fields are exponential decay
+/// rates: higher values catch up faster.
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct PlayerCameraSettings {
+    /// Offset from the player to the point the
+    /// camera orbits.
+    pub offset: Vec3,
+    pub target_decay: f32,
+    /// Decay for yaw and pitch.
+    pub rotation_decay: f32,
+    pub distance_decay: f32,
+    /// How far the player can move vertically
+    /// while in the air before the camera
+    /// follows, so small jumps don't bob the
+    /// camera.
+    pub vertical_dead_zone: f32,
+    /// Seconds of the player's horizontal
+    /// velocity to lead the target by.
+    pub look_ahead: f32,
+    /// Targets further away than this are snapped
+    /// to instead of eased towards, such as after
+    /// a respawn.
+    pub snap_distance: f32,
+    /// Closest the player can zoom in, unless the
+    /// level has a [`LevelCameraZoom`].
+    pub min_distance: f32,
+    /// Furthest the player can zoom out, unless
+    /// the level has a [`LevelCameraZoom`].
+    pub max_distance: f32,
+    /// Distance zoomed per mouse wheel line.
+    pub scroll_zoom_step: f32,
+    /// Distance zoomed per second with the
+    /// gamepad triggers fully pressed.
+    pub gamepad_zoom_speed: f32,
+    /// Extra distance while holding something to
+    /// throw.
+    pub holding_zoom_out: f32,
+    /// Extra distance while standing on a moving
+    /// platform.
+    pub platform_zoom_out: f32,
+    /// Decay for blending into and out of
+    /// authored camera shots.
+    pub shot_blend_decay: f32,
+}
+
+impl PlayerCameraSettings {
+    /// The zoom range, preferring the level's
+    /// override if it has one.
+    pub fn distance_limits(
+        &self,
+        level: Option<&LevelCameraZoom>,
+    ) -> (f32, f32) {
+        level.map_or(
+            (self.min_distance, self.max_distance),
+            |level| {
+                (level.min_distance, level.max_distance)
+            },
+        )
+    }
+}
+
+impl Default for PlayerCameraSettings {
+    fn default() -> Self {
+        Self {
+            offset: Vec3::new(0., 3., 0.),
+            target_decay: 8.,
+            rotation_decay: 25.,
+            distance_decay: 6.,
+            vertical_dead_zone: 1.5,
+            look_ahead: 0.25,
+            snap_distance: 15.,
+            min_distance: 4.,
+            max_distance: 24.,
+            scroll_zoom_step: 1.,
+            gamepad_zoom_speed: 10.,
+            holding_zoom_out: 3.,
+            platform_zoom_out: 4.,
+            shot_blend_decay: 3.,
+        }
+    }
+}
+
+/// Point the rig at the player, applying the
+/// vertical dead zone and look-ahead.
+fn target_camera_to_player(
+    mut camera_rigs: Query<(&mut CameraRig, &PlayerIndex)>,
+    players: Query<
+        (&Transform, &LinearVelocity, &PlayerIndex),
+        With<Player>,
+    >,
+    settings: Res<PlayerCameraSettings>,
+) {
+    for (mut camera_rig, index) in &mut camera_rigs {
+        let Some((transform, velocity, _)) = players
+            .iter()
+            .find(|(_, _, other)| *other == index)
+        else {
+            continue;
+        };
+
+        let mut target = transform.translation
+            + settings.offset
+            + velocity.with_y(0.) * settings.look_ahead;
+
+        // only follow vertically once the player
+        // leaves the dead zone, unless they've come
+        // to rest at a new height
+        if velocity.y.abs() > 0.1 {
+            target.y = camera_rig.target.y.clamp(
+                target.y - settings.vertical_dead_zone,
+                target.y + settings.vertical_dead_zone,
+            );
+        }
+
+        camera_rig.target = target;
+    }
+}
+
+/// Camera zoom limits for a level. Add it to any
+/// entity in the level scene.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+pub struct LevelCameraZoom {
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// The distance the camera starts the level
+    /// at.
+    pub distance: f32,
+}
+
+fn on_add_level_camera_zoom(
+    trigger: Trigger<OnAdd, LevelCameraZoom>,
+    zooms: Query<&LevelCameraZoom>,
+    mut camera_rigs: Query<&mut CameraRig>,
+) {
+    let Ok(zoom) = zooms.get(trigger.entity()) else {
+        return;
+    };
+    for mut camera_rig in &mut camera_rigs {
+        camera_rig.distance = zoom
+            .distance
+            .clamp(zoom.min_distance, zoom.max_distance);
+    }
+}
+
+/// Pull the camera back when the player needs a
+/// wider view.
+fn auto_zoom(
+    mut camera_rigs: Query<(&mut CameraRig, &PlayerIndex)>,
+    players: Query<
+        (&Holding, &TnuaProximitySensor, &PlayerIndex),
+        With<Player>,
+    >,
+    platforms: Query<(), With<Platform>>,
+    parents: Query<&Parent>,
+    settings: Res<PlayerCameraSettings>,
+) {
+    for (mut camera_rig, index) in &mut camera_rigs {
+        let Some((holding, sensor, _)) = players
+            .iter()
+            .find(|(_, _, other)| *other == index)
+        else {
+            continue;
+        };
+
+        // the platform's collider may be a child of
+        // the entity with the `Platform` marker
+        let on_platform =
+            sensor.output.as_ref().is_some_and(|output| {
+                std::iter::once(output.entity)
+                    .chain(
+                        parents
+                            .iter_ancestors(output.entity),
+                    )
+                    .any(|entity| {
+                        platforms.get(entity).is_ok()
+                    })
+            });
+
+        let mut zoom = 0.;
+        if holding.is_some() {
+            zoom += settings.holding_zoom_out;
+        }
+        if on_platform {
+            zoom += settings.platform_zoom_out;
+        }
+        camera_rig.auto_zoom = zoom;
+    }
+}
 
 fn control_camera(
-    camera: Single<
-        (&mut Transform, &CameraRig),
-        Without<Player>,
+    mut cameras: Query<
+        (
+            &mut Transform,
+            &CameraRig,
+            &mut SmoothedCameraRig,
+            &mut CameraCollision,
+        ),
+        (Without<Player>, Without<FreeCamera>),
     >,
+    players: Query<(Entity, &Holding), With<Player>>,
+    sensors: Query<Entity, With<Sensor>>,
+    spatial_query: SpatialQuery,
+    settings: Res<PlayerCameraSettings>,
+    time: Res<Time>,
 ) {
-    let (mut transform, rig) = camera.into_inner();
-
-    let looking_direction = Quat::from_rotation_y(-rig.yaw)
-        * Quat::from_rotation_x(
-            // TODO: .clamp is to prevent camera rotating
-            // through ground
-            // is not a permanent solution
-            rig.pitch, //.clamp(0., FRAC_PI_2),
-        )
-        * Vec3::NEG_Z;
+    let delta = time.delta_secs();
+
+    // Players, what they're carrying, and trigger
+    // volumes shouldn't push the camera in
+    let filter = SpatialQueryFilter::from_excluded_entities(
+        players
+            .iter()
+            .flat_map(|(entity, holding)| {
+                std::iter::once(entity).chain(holding.0)
+            })
+            .chain(&sensors),
+    );
+
+    for (mut transform, rig, mut smoothed, mut collision) in
+        &mut cameras
+    {
+        if smoothed.target.distance(rig.target)
+            > settings.snap_distance
+        {
+            *smoothed = SmoothedCameraRig::from(rig);
+        }
+        smoothed.target.smooth_nudge(
+            &rig.target,
+            settings.target_decay,
+            delta,
+        );
+        smoothed.yaw.smooth_nudge(
+            &rig.yaw,
+            settings.rotation_decay,
+            delta,
+        );
+        smoothed.pitch.smooth_nudge(
+            &rig.pitch,
+            settings.rotation_decay,
+            delta,
+        );
+        smoothed.distance.smooth_nudge(
+            &(rig.distance + rig.auto_zoom),
+            settings.distance_decay,
+            delta,
+        );
+
+        let looking_direction =
+            Quat::from_rotation_y(-smoothed.yaw)
+                * Quat::from_rotation_x(smoothed.pitch)
+                * Vec3::NEG_Z;
+
+        // sweep from the target back to where the
+        // camera wants to be, stopping short of
+        // anything in the way
+        let unobstructed_distance =
+            Dir3::new(-looking_direction)
+                .ok()
+                .and_then(|direction| {
+                    spatial_query.cast_shape(
+                        &Collider::sphere(collision.radius),
+                        smoothed.target,
+                        Quat::IDENTITY,
+                        direction,
+                        &ShapeCastConfig::from_max_distance(
+                            smoothed.distance,
+                        ),
+                        &filter,
+                    )
+                })
+                .map(|hit| hit.distance)
+                .unwrap_or(smoothed.distance);
+
+        if unobstructed_distance
+            < collision.current_distance
+        {
+            // snap in so the camera never sees through
+            // the obstruction
+            collision.current_distance =
+                unobstructed_distance;
+        } else {
+            let recovery_speed = collision.recovery_speed;
+            collision.current_distance.smooth_nudge(
+                &unobstructed_distance,
+                recovery_speed,
+                delta,
+            );
+        }
 
-    transform.translation =
-        rig.target - rig.distance * looking_direction;
-    transform.look_at(rig.target, Dir3::Y);
+        transform.translation = smoothed.target
+            - collision.current_distance
+                * looking_direction;
+        transform.look_at(smoothed.target, Dir3::Y);
+    }
+}
+
+/// Where the camera currently is on the rig. It
+/// eases towards the [`CameraRig`] using the
+/// [`PlayerCameraSettings`].
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+pub struct SmoothedCameraRig {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    pub target: Vec3,
+}
+
+impl Default for SmoothedCameraRig {
+    fn default() -> Self {
+        Self::from(&CameraRig::default())
+    }
+}
+
+impl From<&CameraRig> for SmoothedCameraRig {
+    fn from(rig: &CameraRig) -> Self {
+        Self {
+            yaw: rig.yaw,
+            pitch: rig.pitch,
+            distance: rig.distance,
+            target: rig.target,
+        }
+    }
+}
+
+/// Keeps the camera in front of level geometry
+/// between it and the [`CameraRig`] target.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+pub struct CameraCollision {
+    /// Radius of the sphere swept from the target
+    /// towards the camera.
+    pub radius: f32,
+    /// Decay rate used to ease back out to the
+    /// full [`CameraRig::distance`] once the view
+    /// is clear.
+    pub recovery_speed: f32,
+    /// The distance the camera is currently
+    /// placed at.
+    pub current_distance: f32,
+}
+
+impl Default for CameraCollision {
+    fn default() -> Self {
+        Self {
+            radius: 0.3,
+            recovery_speed: 4.,
+            current_distance: CameraRig::default().distance,
+        }
+    }
 }
 
 /// Camera movement component.
@@ -70,11 +424,14 @@ pub struct CameraRig {
     pub yaw: f32,
     /// Rotation around the horizontal axis of the
     /// camera (radians) (-pi/2; pi/2).
-    /// Positive looks down from above.
+    /// Negative looks down from above.
     pub pitch: f32,
     /// Distance from the center, smaller distance
     /// causes more zoom.
     pub distance: f32,
+    /// Extra distance added on top of `distance`
+    /// by gameplay, such as while holding a box.
+    pub auto_zoom: f32,
     /// Location in 3D space at which the camera
     /// is looking and around which it is
     /// orbiting.
@@ -85,8 +442,9 @@ impl Default for CameraRig {
     fn default() -> Self {
         Self {
             yaw: PI,
-            pitch: 0.45,
+            pitch: -0.45,
             distance: 12.0,
+            auto_zoom: 0.,
             target: Vec3::ZERO,
         }
     }
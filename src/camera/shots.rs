@@ -0,0 +1,276 @@
+use avian3d::prelude::{CollidingEntities, Sensor};
+use bevy::prelude::*;
+
+use super::{PlayerCamera, PlayerCameraSettings};
+use crate::{
+    Player, PlayerIndex,
+    free_camera::FreeCamera,
+    level_spawn::{LevelState, SpawnPlayerEvent},
+};
+
+pub struct CameraShotsPlugin;
+
+impl Plugin for CameraShotsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<IntroShot>()
+            .register_type::<FixedCameraZone>()
+            .add_observer(on_spawn_player)
+            .add_systems(
+                Update,
+                (
+                    advance_intro.run_if(
+                        resource_exists::<IntroFlyover>,
+                    ),
+                    select_camera_shot.never_param_warn(),
+                )
+                    .chain()
+                    .run_if(in_state(LevelState::Level)),
+            )
+            .add_systems(
+                OnExit(LevelState::Level),
+                |mut commands: Commands| {
+                    commands
+                        .remove_resource::<IntroFlyover>();
+                },
+            );
+    }
+}
+
+/// A stop on the level intro flyover. Place it on
+/// an empty in Blender; the camera looks down the
+/// empty's -Z axis.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+pub struct IntroShot {
+    /// Shots are visited in ascending order.
+    pub order: u32,
+    /// Seconds spent travelling to the next shot,
+    /// or holding on the last one.
+    pub duration: f32,
+}
+
+/// Holds the camera at a fixed shot while the
+/// player is inside this volume. The volume also
+/// needs a collider.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+#[require(Sensor, CollidingEntities)]
+pub struct FixedCameraZone {
+    /// [`Name`] of the empty the camera is placed
+    /// at.
+    pub shot: String,
+    /// Turn to keep the player in view instead of
+    /// keeping the empty's rotation.
+    pub track_player: bool,
+}
+
+/// An authored pose taking over from the
+/// [`CameraRig`](super::CameraRig).
+#[derive(Component, Default, Debug)]
+pub struct CameraShot {
+    pub pose: Transform,
+    pub active: bool,
+    /// How far the camera has blended from the
+    /// rig (0) to the shot (1).
+    pub weight: f32,
+}
+
+/// The intro playing for the current level.
+#[derive(Resource, Debug)]
+struct IntroFlyover {
+    /// Each shot's pose and duration, in order.
+    shots: Vec<(Transform, f32)>,
+    elapsed: f32,
+}
+
+impl IntroFlyover {
+    /// The pose `elapsed` seconds in, or `None`
+    /// once the flyover is over.
+    fn sample(&self) -> Option<Transform> {
+        let mut start = 0.;
+        for (index, (pose, duration)) in
+            self.shots.iter().enumerate()
+        {
+            if self.elapsed < start + duration {
+                let Some((next, _)) =
+                    self.shots.get(index + 1)
+                else {
+                    return Some(*pose);
+                };
+                let t = (self.elapsed - start) / duration;
+                // ease in and out of every stop
+                let t = t * t * (3. - 2. * t);
+                return Some(Transform {
+                    translation: pose
+                        .translation
+                        .lerp(next.translation, t),
+                    rotation: pose
+                        .rotation
+                        .slerp(next.rotation, t),
+                    ..*pose
+                });
+            }
+            start += duration;
+        }
+        None
+    }
+}
+
+/// Start the intro once the level scene, and so
+/// its shots, have spawned. Every player watches
+/// the same intro.
+fn on_spawn_player(
+    trigger: Trigger<SpawnPlayerEvent>,
+    mut commands: Commands,
+    shots: Query<(Entity, &IntroShot)>,
+    helper: TransformHelper,
+    mut camera_shots: Query<&mut CameraShot>,
+) {
+    if trigger.player.0 != 0 {
+        return;
+    }
+
+    let mut shots = shots.iter().collect::<Vec<_>>();
+    shots.sort_by_key(|(_, shot)| shot.order);
+
+    let shots = shots
+        .into_iter()
+        .filter_map(|(entity, shot)| {
+            let pose = helper
+                .compute_global_transform(entity)
+                .ok()?
+                .compute_transform();
+            Some((pose, shot.duration))
+        })
+        .collect::<Vec<_>>();
+
+    let Some((first, _)) = shots.first() else {
+        return;
+    };
+
+    // cut straight to the first shot rather than
+    // blending from wherever the last level left
+    // the camera
+    for mut camera_shot in &mut camera_shots {
+        camera_shot.pose = *first;
+        camera_shot.active = true;
+        camera_shot.weight = 1.;
+    }
+
+    commands.insert_resource(IntroFlyover {
+        shots,
+        elapsed: 0.,
+    });
+}
+
+/// Tick the intro, ending it early on any button
+/// press.
+fn advance_intro(
+    mut commands: Commands,
+    mut intro: ResMut<IntroFlyover>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    time: Res<Time>,
+) {
+    intro.elapsed += time.delta_secs();
+
+    let skipped = keys.get_just_pressed().next().is_some()
+        || mouse.get_just_pressed().next().is_some()
+        || gamepads.iter().any(|gamepad| {
+            gamepad.get_just_pressed().next().is_some()
+        });
+
+    if skipped || intro.sample().is_none() {
+        commands.remove_resource::<IntroFlyover>();
+    }
+}
+
+/// Pick the shot overriding each player's rig:
+/// the intro, then any fixed camera zone the
+/// player is in.
+fn select_camera_shot(
+    mut cameras: Query<(&mut CameraShot, &PlayerIndex)>,
+    intro: Option<Res<IntroFlyover>>,
+    players: Query<
+        (Entity, &GlobalTransform, &PlayerIndex),
+        With<Player>,
+    >,
+    zones: Query<(&FixedCameraZone, &CollidingEntities)>,
+    named: Query<(&Name, &GlobalTransform)>,
+) {
+    let intro_pose = intro.and_then(|intro| intro.sample());
+
+    for (mut camera_shot, index) in &mut cameras {
+        if let Some(pose) = intro_pose {
+            camera_shot.pose = pose;
+            camera_shot.active = true;
+            continue;
+        }
+
+        let Some((player, player_transform, _)) = players
+            .iter()
+            .find(|(_, _, other)| *other == index)
+        else {
+            camera_shot.active = false;
+            continue;
+        };
+
+        let zone_pose = zones
+            .iter()
+            .filter(|(_, colliding)| {
+                colliding.contains(&player)
+            })
+            .find_map(|(zone, _)| {
+                let (_, shot) =
+                    named.iter().find(|(name, _)| {
+                        name.as_str() == zone.shot
+                    })?;
+                let mut pose = shot.compute_transform();
+                if zone.track_player {
+                    pose.look_at(
+                        player_transform.translation(),
+                        Dir3::Y,
+                    );
+                }
+                Some(pose)
+            });
+
+        match zone_pose {
+            Some(pose) => {
+                camera_shot.pose = pose;
+                camera_shot.active = true;
+            }
+            None => camera_shot.active = false,
+        }
+    }
+}
+
+/// Blend the rig's placement of the camera
+/// towards the active shot, and back out again
+/// once it ends.
+pub(super) fn apply_camera_shot(
+    mut cameras: Query<
+        (&mut Transform, &mut CameraShot),
+        (With<PlayerCamera>, Without<FreeCamera>),
+    >,
+    settings: Res<PlayerCameraSettings>,
+    time: Res<Time>,
+) {
+    for (mut transform, mut shot) in &mut cameras {
+        let goal = if shot.active { 1. } else { 0. };
+        shot.weight.smooth_nudge(
+            &goal,
+            settings.shot_blend_decay,
+            time.delta_secs(),
+        );
+
+        let weight = shot.weight;
+        transform.translation = transform
+            .translation
+            .lerp(shot.pose.translation, weight);
+        transform.rotation = transform
+            .rotation
+            .slerp(shot.pose.rotation, weight);
+    }
+}
@@ -1,16 +1,28 @@
+use avian3d::prelude::{
+    Collider, LinearVelocity, ShapeCastConfig,
+    SpatialQuery, SpatialQueryFilter,
+};
 use bevy::prelude::*;
-use bevy_tnua::prelude::*;
+use bevy_tnua::{TnuaProximitySensor, prelude::*};
 use bevy_tnua_avian3d::*;
 use leafwing_input_manager::prelude::*;
-use std::f32::consts::FRAC_PI_4;
 
-use crate::{camera::CameraRig, Holding, Player};
+use crate::{
+    Holding, Player, PlayerIndex,
+    camera::{
+        CameraRig, LevelCameraZoom, PlayerCameraSettings,
+    },
+    cursor::CursorGrab,
+    replay::replay_playing,
+    settings::LookSettings,
+};
 
 pub struct ControlsPlugin;
 
 impl Plugin for ControlsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((
+        app.register_type::<LevelAbilities>()
+        .add_plugins((
             TnuaControllerPlugin::new(FixedUpdate),
             TnuaAvian3dPlugin::new(FixedUpdate),
             InputManagerPlugin::<Action>::default(),
@@ -21,8 +33,11 @@ impl Plugin for ControlsPlugin {
                 apply_controls
                     .never_param_warn()
                     .in_set(TnuaUserControlsSystemSet),
-                handle_pantilt.never_param_warn(),
-                target_camera_to_player.never_param_warn().before(apply_controls)
+                handle_pantilt
+                    .never_param_warn()
+                    .run_if(not(replay_playing))
+                    .before(apply_controls),
+                handle_zoom.never_param_warn(),
             ),
         )
         // .add_systems(
@@ -52,110 +67,486 @@ impl Plugin for ControlsPlugin {
 pub enum Action {
     #[actionlike(DualAxis)]
     Move,
+    /// Mouse look, in pixels of movement.
     #[actionlike(DualAxis)]
     PanTilt,
+    /// Gamepad stick look, as stick deflection.
+    #[actionlike(DualAxis)]
+    PanTiltGamepad,
     Run,
     Jump,
     Interact,
+    Dash,
+    Crouch,
+    /// Mouse wheel zoom, in lines scrolled.
+    #[actionlike(Axis)]
+    Zoom,
+    /// Gamepad trigger zoom, as trigger pressure.
+    #[actionlike(Axis)]
+    ZoomGamepad,
+}
+
+/// How close the ground has to be for the player
+/// to count as standing on it, measured from the
+/// player's center.
+const GROUNDED_PROXIMITY: f32 = 1.1;
+const STANDING_RADIUS: f32 = 0.5;
+const STANDING_LENGTH: f32 = 0.5;
+/// How far the character floats above the ground,
+/// measured from its center.
+const FLOAT_HEIGHT: f32 = 1.;
+/// How far crouching lowers the character.
+const CROUCH_FLOAT_OFFSET: f32 = -0.5;
+/// Height of the top of the standing collider
+/// above the crouching character's center, which
+/// has to be clear to stand up.
+const STANDING_HEADROOM: f32 = STANDING_LENGTH / 2.
+    + STANDING_RADIUS
+    - CROUCH_FLOAT_OFFSET;
+/// How far from the player's center to look for
+/// walls to slide down or jump off.
+const WALL_PROBE_DISTANCE: f32 = 0.7;
+/// Fastest the player falls while sliding down a
+/// wall.
+const WALL_SLIDE_SPEED: f32 = 2.;
+/// Speed added away from the wall by a wall jump.
+const WALL_JUMP_PUSH: f32 = 8.;
+/// Seconds after a wall jump that the player
+/// keeps moving away from the wall, rather than
+/// steering straight back into it.
+const WALL_JUMP_STEER_LOCK: f32 = 0.3;
+const DASH_DISTANCE: f32 = 6.;
+
+/// The movement abilities a level allows. Add it
+/// to any entity in the level scene; levels
+/// without one allow every ability.
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component, Default)]
+pub struct LevelAbilities {
+    pub dash: bool,
+    pub crouch: bool,
+    pub wall_jump: bool,
+}
+
+impl Default for LevelAbilities {
+    fn default() -> Self {
+        Self {
+            dash: true,
+            crouch: true,
+            wall_jump: true,
+        }
+    }
+}
+
+/// Per-player state for the movement abilities.
+#[derive(Component, Default, Debug)]
+pub struct AbilityState {
+    pub crouching: bool,
+    /// Only one dash is allowed between touching
+    /// the ground.
+    pub air_dash_used: bool,
+    /// Where the last wall jump pushed the
+    /// player, and for how many more seconds
+    /// the walk follows it instead of the
+    /// stick.
+    pub wall_jump_push: Vec3,
+    pub wall_jump_secs: f32,
+}
+
+/// The default bindings for a player, keyboard
+/// and mouse for player one and a gamepad for
+/// everyone.
+pub fn player_input_map(
+    index: PlayerIndex,
+) -> InputMap<Action> {
+    let mut input_map = gamepad_input_map();
+    if index.0 == 0 {
+        input_map.merge(&keyboard_mouse_input_map());
+    }
+    input_map
+}
+
+fn keyboard_mouse_input_map() -> InputMap<Action> {
+    InputMap::new([
+        (Action::Jump, KeyCode::Space),
+        (Action::Interact, KeyCode::KeyE),
+    ])
+    .with_multiple([
+        (Action::Dash, KeyCode::ShiftLeft),
+        (Action::Crouch, KeyCode::ControlLeft),
+    ])
+    .with_dual_axis(Action::Move, VirtualDPad::wasd())
+    .with_dual_axis(Action::PanTilt, MouseMove::default())
+    .with_axis(Action::Zoom, MouseScrollAxis::Y)
+}
+
+fn gamepad_input_map() -> InputMap<Action> {
+    InputMap::default()
+        .with_multiple([
+            (Action::Interact, GamepadButton::RightTrigger),
+            (Action::Jump, GamepadButton::South),
+            (Action::Dash, GamepadButton::West),
+            (Action::Crouch, GamepadButton::East),
+        ])
+        .with_dual_axis(
+            Action::Move,
+            GamepadStick::LEFT.with_deadzone_symmetric(0.1),
+        )
+        .with_dual_axis(
+            Action::PanTiltGamepad,
+            GamepadStick::RIGHT
+                .with_deadzone_symmetric(0.1),
+        )
+        .with_axis(
+            Action::ZoomGamepad,
+            VirtualAxis::new(
+                GamepadButton::RightTrigger2,
+                GamepadButton::LeftTrigger2,
+            ),
+        )
 }
 
-fn handle_pantilt(
-    action_state: Single<
-        &ActionState<Action>,
+pub(crate) fn handle_pantilt(
+    players: Query<
+        (&ActionState<Action>, &PlayerIndex),
         With<Player>,
     >,
-    mut camera_rig: Single<&mut CameraRig>,
+    mut camera_rigs: Query<(&mut CameraRig, &PlayerIndex)>,
+    settings: Res<LookSettings>,
+    cursor: Res<CursorGrab>,
+    time: Res<Time>,
 ) {
-    let axis_pair =
-        action_state.axis_pair(&Action::PanTilt);
+    for (mut camera_rig, index) in &mut camera_rigs {
+        let Some((action_state, _)) = players
+            .iter()
+            .find(|(_, other)| *other == index)
+        else {
+            continue;
+        };
+        // the mouse belongs to the UI while released
+        let mouse = if cursor.grabbed {
+            action_state.axis_pair(&Action::PanTilt)
+                * settings.mouse_sensitivity
+        } else {
+            Vec2::ZERO
+        };
 
-    camera_rig.yaw += axis_pair.x / 90.;
-    camera_rig.pitch -= axis_pair.y / 90.;
-    camera_rig.pitch =
-        camera_rig.pitch.clamp(-FRAC_PI_4, 0.);
+        // the stick is a rate rather than a distance, so
+        // scale it by the tick length to keep look speed
+        // independent of the fixed timestep
+        let stick = settings
+            .gamepad_response
+            .apply(action_state.clamped_axis_pair(
+                &Action::PanTiltGamepad,
+            ))
+            * settings.gamepad_sensitivity
+            * time.delta_secs();
+
+        let delta = (mouse + stick) * settings.inversion();
+
+        camera_rig.yaw += delta.x;
+        camera_rig.pitch -= delta.y;
+        camera_rig.pitch = camera_rig
+            .pitch
+            .clamp(settings.min_pitch, settings.max_pitch);
+    }
 }
 
-fn target_camera_to_player(
-    mut camera_rig: Single<&mut CameraRig>,
-    transform: Single<
-        &Transform,
-        (Changed<Transform>, With<Player>),
+fn handle_zoom(
+    players: Query<
+        (&ActionState<Action>, &PlayerIndex),
+        With<Player>,
     >,
+    mut camera_rigs: Query<(&mut CameraRig, &PlayerIndex)>,
+    settings: Res<PlayerCameraSettings>,
+    level_zoom: Option<Single<&LevelCameraZoom>>,
+    cursor: Res<CursorGrab>,
+    time: Res<Time>,
 ) {
-    camera_rig.target = transform
-        .translation
-        .with_y(transform.translation.y + 3.);
+    let (min, max) = settings
+        .distance_limits(level_zoom.as_deref().copied());
+
+    for (mut camera_rig, index) in &mut camera_rigs {
+        let Some((action_state, _)) = players
+            .iter()
+            .find(|(_, other)| *other == index)
+        else {
+            continue;
+        };
+
+        // the scroll wheel belongs to the UI while the
+        // cursor is released
+        let scroll = if cursor.grabbed {
+            action_state.value(&Action::Zoom)
+                * settings.scroll_zoom_step
+        } else {
+            0.
+        };
+        let triggers = action_state
+            .value(&Action::ZoomGamepad)
+            * settings.gamepad_zoom_speed
+            * time.delta_secs();
+
+        camera_rig.distance =
+            (camera_rig.distance - scroll + triggers)
+                .clamp(min, max);
+    }
 }
 
-fn apply_controls(
-    camera_transform: Single<&Transform, With<Camera3d>>,
-    mut player: Single<(&mut TnuaController, &Holding)>,
-    action_state: Single<
-        &ActionState<Action>,
+pub(crate) fn apply_controls(
+    mut commands: Commands,
+    mut players: Query<
+        (
+            Entity,
+            &Transform,
+            &mut TnuaController,
+            &TnuaProximitySensor,
+            &mut LinearVelocity,
+            &mut AbilityState,
+            &Holding,
+            &ActionState<Action>,
+            &PlayerIndex,
+        ),
         With<Player>,
     >,
-    camera_rig: Single<&CameraRig>,
+    camera_rigs: Query<(&CameraRig, &PlayerIndex)>,
+    level_abilities: Option<Single<&LevelAbilities>>,
+    spatial_query: SpatialQuery,
+    time: Res<Time>,
 ) {
-    let axis_pair =
-        action_state.clamped_axis_pair(&Action::Move);
-
-    let forward =
-        camera_transform.forward().xz() * axis_pair.y;
-    let horizontal =
-        camera_transform.right().xz() * axis_pair.x;
-    let force = forward + horizontal;
-    let direction = Vec3::new(force.x, 0., force.y);
-
-    let looking_direction =
-        Quat::from_rotation_y(-camera_rig.yaw)
-            * Quat::from_rotation_x(camera_rig.pitch)
-            * Vec3::NEG_Z;
-
-    let (mut controller, holding) = player.into_inner();
-
-    // Feed the basis every frame. Even if the player
-    // doesn't move - just use `desired_velocity:
-    // Vec3::ZERO`. `TnuaController` starts without a
-    // basis, which will make the character collider
-    // just fall.
-    controller.basis(TnuaBuiltinWalk {
-        // The `desired_velocity` determines how the
-        // character will move.
-        desired_velocity: direction.normalize_or_zero()
-            * 10.0,
-        desired_forward: Dir3::new(
-            looking_direction.normalize(),
-        )
-        .ok(),
-        // The `float_height` must be greater (even if by
-        // little) from the distance between the
-        // character's center and the lowest point of its
-        // collider.
-        float_height: 1.,
-        // `TnuaBuiltinWalk` has many other fields for
-        // customizing the movement - but they have
-        // sensible defaults. Refer to the
-        // `TnuaBuiltinWalk`'s documentation to learn what
-        // they do.
-        ..default()
-    });
-    // Feed the jump action every frame as long as the
-    // player holds the jump button. If the player
-    // stops holding the jump button, simply stop
-    // feeding the action.
-    if action_state.pressed(&Action::Jump) {
-        controller.action(TnuaBuiltinJump {
-            // The height is the only mandatory field of the
-            // jump button.
-            height: if holding.0.is_some() {
-                2.0
-            }else {
-                4.0
-            },
-            shorten_extra_gravity: 120.,
-            // `TnuaBuiltinJump` also has customization
-            // fields with sensible defaults.
+    let abilities = level_abilities
+        .map(|abilities| abilities.clone())
+        .unwrap_or_default();
+
+    for (
+        entity,
+        transform,
+        mut controller,
+        sensor,
+        mut linear_velocity,
+        mut ability_state,
+        holding,
+        action_state,
+        index,
+    ) in &mut players
+    {
+        let Some((camera_rig, _)) = camera_rigs
+            .iter()
+            .find(|(_, other)| *other == index)
+        else {
+            continue;
+        };
+
+        let axis_pair =
+            action_state.clamped_axis_pair(&Action::Move);
+
+        let looking_direction =
+            Quat::from_rotation_y(-camera_rig.yaw)
+                * Quat::from_rotation_x(camera_rig.pitch)
+                * Vec3::NEG_Z;
+
+        // movement is relative to the rig rather than
+        // the camera's Transform so that it only depends
+        // on state that is updated in FixedUpdate
+        let camera_forward =
+            looking_direction.xz().normalize_or_zero();
+        let camera_right =
+            Vec2::new(-camera_forward.y, camera_forward.x);
+
+        let forward = camera_forward * axis_pair.y;
+        let horizontal = camera_right * axis_pair.x;
+        let force = forward + horizontal;
+        let direction = Vec3::new(force.x, 0., force.y);
+
+        let grounded =
+            sensor.output.as_ref().is_some_and(|output| {
+                output.proximity <= GROUNDED_PROXIMITY
+            });
+        if grounded {
+            ability_state.air_dash_used = false;
+        }
+
+        // the player shouldn't find itself, or the box
+        // it's carrying, when probing for walls and
+        // ceilings
+        let filter =
+            SpatialQueryFilter::from_excluded_entities(
+                std::iter::once(entity).chain(holding.0),
+            );
+
+        // Keep crouching while there's no room to stand
+        // up, even if the button has been released. A
+        // sphere as wide as the standing collider is
+        // swept up to where its top would be, so the
+        // edges of a ceiling count too.
+        let crouching = abilities.crouch
+            && (action_state.pressed(&Action::Crouch)
+                || (ability_state.crouching
+                    && spatial_query
+                        .cast_shape(
+                            &Collider::sphere(
+                                STANDING_RADIUS,
+                            ),
+                            transform.translation,
+                            Quat::IDENTITY,
+                            Dir3::Y,
+                            &ShapeCastConfig {
+                                max_distance:
+                                    STANDING_HEADROOM
+                                        - STANDING_RADIUS,
+                                // the sphere starts out
+                                // touching the ground
+                                ignore_origin_penetration:
+                                    true,
+                                ..default()
+                            },
+                            &filter,
+                        )
+                        .is_some()));
+        if crouching != ability_state.crouching {
+            ability_state.crouching = crouching;
+            commands.entity(entity).insert(if crouching {
+                crouching_collider()
+            } else {
+                standing_collider()
+            });
+        }
+
+        // a wall in the direction the player is pushing,
+        // while they're in the air
+        let wall_normal = Dir3::new(direction)
+            .ok()
+            .filter(|_| abilities.wall_jump && !grounded)
+            .and_then(|direction| {
+                spatial_query.cast_ray(
+                    transform.translation,
+                    direction,
+                    WALL_PROBE_DISTANCE,
+                    true,
+                    &filter,
+                )
+            })
+            .map(|hit| hit.normal)
+            .filter(|normal| normal.y.abs() < 0.3);
+
+        if wall_normal.is_some()
+            && linear_velocity.y < -WALL_SLIDE_SPEED
+        {
+            linear_velocity.y = -WALL_SLIDE_SPEED;
+        }
+
+        ability_state.wall_jump_secs -= time.delta_secs();
+        let desired_velocity =
+            if ability_state.wall_jump_secs > 0. {
+                ability_state.wall_jump_push
+            } else {
+                direction.normalize_or_zero()
+                    * if crouching { 5.0 } else { 10.0 }
+            };
+
+        // Feed the basis every frame. Even if the player
+        // doesn't move - just use `desired_velocity:
+        // Vec3::ZERO`. `TnuaController` starts without a
+        // basis, which will make the character collider
+        // just fall.
+        controller.basis(TnuaBuiltinWalk {
+            // The `desired_velocity` determines how the
+            // character will move.
+            desired_velocity,
+            desired_forward: Dir3::new(
+                looking_direction.normalize(),
+            )
+            .ok(),
+            // The `float_height` must be greater (even if
+            // by little) from the distance
+            // between the character's center
+            // and the lowest point of its
+            // collider.
+            float_height: FLOAT_HEIGHT,
+            // `TnuaBuiltinWalk` has many other fields for
+            // customizing the movement - but they have
+            // sensible defaults. Refer to the
+            // `TnuaBuiltinWalk`'s documentation to learn
+            // what they do.
             ..default()
         });
+
+        // Only one action runs at a time, so they're fed
+        // in priority order.
+        if abilities.dash
+            && action_state.just_pressed(&Action::Dash)
+            && (grounded || !ability_state.air_dash_used)
+        {
+            if !grounded {
+                ability_state.air_dash_used = true;
+            }
+            // dash where the player is pushing, or where
+            // they're looking if they aren't
+            let dash_direction = Dir3::new(direction)
+                .or_else(|_| {
+                    Dir3::new(looking_direction.with_y(0.))
+                })
+                .unwrap_or(Dir3::NEG_Z);
+
+            // The dash continues on its own once started,
+            // so it only needs to be fed once.
+            controller.action(TnuaBuiltinDash {
+                displacement: dash_direction
+                    * DASH_DISTANCE,
+                desired_forward: Some(dash_direction),
+                allow_in_air: true,
+                ..default()
+            });
+        } else if action_state.pressed(&Action::Jump) {
+            // Feed the jump action every frame as long as
+            // the player holds the jump button. If the
+            // player stops holding the jump button, simply
+            // stop feeding the action.
+            if let Some(normal) = wall_normal {
+                if action_state.just_pressed(&Action::Jump)
+                {
+                    // push off the wall, and keep the walk
+                    // from steering straight back
+                    let push = normal * WALL_JUMP_PUSH;
+                    linear_velocity.0 += push;
+                    ability_state.wall_jump_push = push;
+                    ability_state.wall_jump_secs =
+                        WALL_JUMP_STEER_LOCK;
+                }
+            }
+            controller.action(TnuaBuiltinJump {
+                // The height is the only mandatory field of
+                // the jump button.
+                height: if holding.0.is_some() {
+                    2.0
+                } else {
+                    4.0
+                },
+                // only wall jumps can start in the air
+                allow_in_air: wall_normal.is_some(),
+                shorten_extra_gravity: 120.,
+                // `TnuaBuiltinJump` also has customization
+                // fields with sensible defaults.
+                ..default()
+            });
+        } else if crouching {
+            controller.action(TnuaBuiltinCrouch {
+                // lower the character so the crouching
+                // collider sits just above the ground
+                float_offset: CROUCH_FLOAT_OFFSET,
+                ..default()
+            });
+        }
     }
 }
+
+/// The player's collider while standing.
+pub fn standing_collider() -> Collider {
+    Collider::capsule(STANDING_RADIUS, STANDING_LENGTH)
+}
+
+/// The player's collider while crouching, short
+/// enough to fit under low platforms.
+pub fn crouching_collider() -> Collider {
+    Collider::capsule(0.4, 0.)
+}
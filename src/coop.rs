@@ -0,0 +1,197 @@
+use bevy::{
+    color::palettes::tailwind::SLATE_950, prelude::*,
+    render::camera::Viewport, window::PrimaryWindow,
+};
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    AppState, Player, PlayerIndex, camera::PlayerCamera,
+    controls::Action, level_spawn::LevelState,
+    post_process::PostProcessSettings,
+    section_texture::SectionsPrepass,
+};
+
+/// The most players that can share the screen.
+pub const MAX_LOCAL_PLAYERS: usize = 4;
+
+pub struct CoopPlugin;
+
+impl Plugin for CoopPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<LocalPlayers>()
+            .register_type::<PlayerIndex>()
+            .init_resource::<LocalPlayers>()
+            .add_systems(
+                Update,
+                (
+                    cycle_local_players,
+                    sync_player_cameras.run_if(
+                        resource_changed::<LocalPlayers>,
+                    ),
+                    layout_viewports.never_param_warn(),
+                    assign_gamepads,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+/// How many players are playing on this machine.
+/// Cycled with F7, which restarts the level.
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct LocalPlayers(pub usize);
+
+impl Default for LocalPlayers {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+fn cycle_local_players(
+    input: Res<ButtonInput<KeyCode>>,
+    mut local_players: ResMut<LocalPlayers>,
+    mut next_state: ResMut<NextState<LevelState>>,
+) {
+    if !input.just_pressed(KeyCode::F7) {
+        return;
+    }
+    local_players.0 =
+        local_players.0 % MAX_LOCAL_PLAYERS + 1;
+    info!(players = local_players.0, "local players");
+    next_state.set(LevelState::Loading);
+}
+
+/// One camera per local player, each rendering
+/// its own outlines.
+fn sync_player_cameras(
+    mut commands: Commands,
+    local_players: Res<LocalPlayers>,
+    cameras: Query<
+        (Entity, &PlayerIndex),
+        With<PlayerCamera>,
+    >,
+) {
+    for (entity, index) in &cameras {
+        if index.0 >= local_players.0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for index in 0..local_players.0 {
+        if cameras.iter().any(|(_, other)| other.0 == index)
+        {
+            continue;
+        }
+        commands.spawn((
+            Name::new(format!("PlayerCamera{index}")),
+            Camera3d::default(),
+            Transform::from_xyz(10., 15., 10.).looking_at(
+                Vec3::new(0.0, 2., 0.0),
+                Vec3::Y,
+            ),
+            // OrderIndependentTransparencySettings::default(),
+            Camera {
+                hdr: true,
+                order: index as isize,
+                ..default()
+            },
+            // Msaa currently doesn't work with OIT
+            Msaa::Off,
+            PostProcessSettings {
+                stroke_color: Color::from(SLATE_950).into(),
+                width: 2,
+            },
+            SectionsPrepass,
+            // DepthPrepass,
+            PlayerCamera,
+            PlayerIndex(index),
+        ));
+    }
+}
+
+/// Split the window between the player cameras:
+/// side by side for two players, a grid for more.
+fn layout_viewports(
+    window: Single<&Window, With<PrimaryWindow>>,
+    local_players: Res<LocalPlayers>,
+    mut cameras: Query<
+        (&mut Camera, &PlayerIndex),
+        With<PlayerCamera>,
+    >,
+) {
+    let size = window.physical_size();
+    let (columns, rows) = match local_players.0 {
+        0 | 1 => (1, 1),
+        2 => (2, 1),
+        _ => (2, 2),
+    };
+    let cell = size / UVec2::new(columns, rows);
+
+    for (mut camera, index) in &mut cameras {
+        let viewport = (local_players.0 > 1
+            && cell.cmpgt(UVec2::ZERO).all())
+        .then(|| {
+            let index = index.0 as u32;
+            Viewport {
+                physical_position: UVec2::new(
+                    index % columns,
+                    index / columns,
+                ) * cell,
+                physical_size: cell,
+                ..default()
+            }
+        });
+
+        if camera.viewport != viewport {
+            camera.viewport = viewport;
+        }
+    }
+}
+
+/// Give each player their own gamepad, in the
+/// order the gamepads connected. Player one
+/// falls back to keyboard and mouse when there
+/// aren't enough gamepads to go around, and a
+/// lone player accepts input from any gamepad.
+fn assign_gamepads(
+    local_players: Res<LocalPlayers>,
+    gamepads: Query<Entity, With<Gamepad>>,
+    mut players: Query<
+        (&mut InputMap<Action>, &PlayerIndex),
+        With<Player>,
+    >,
+) {
+    let mut gamepads = gamepads.iter().collect::<Vec<_>>();
+    gamepads.sort();
+    let keyboard_only = gamepads.len() < local_players.0;
+
+    for (mut input_map, index) in &mut players {
+        let gamepad = (local_players.0 > 1).then(|| {
+            let slot = if keyboard_only {
+                index.0.checked_sub(1)
+            } else {
+                Some(index.0)
+            };
+            // `None` would accept every gamepad, so
+            // players without one are bound to one
+            // that never exists
+            slot.and_then(|slot| {
+                gamepads.get(slot).copied()
+            })
+            .unwrap_or(Entity::PLACEHOLDER)
+        });
+
+        if input_map.gamepad() != gamepad {
+            match gamepad {
+                Some(gamepad) => {
+                    input_map.set_gamepad(gamepad);
+                }
+                None => {
+                    input_map.clear_gamepad();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use bevy::{
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+
+use crate::{
+    dev::InspectorVisible, settings::SettingsVisible,
+};
+
+pub struct CursorPlugin;
+
+impl Plugin for CursorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CursorGrab>().add_systems(
+            Update,
+            grab_cursor.never_param_warn(),
+        );
+    }
+}
+
+/// Whether the cursor is locked to the window for
+/// mouse look. Mouse look input is ignored while
+/// it isn't.
+#[derive(Resource, Default, PartialEq, Eq)]
+pub struct CursorGrab {
+    pub grabbed: bool,
+}
+
+/// Lock and hide the cursor while playing, and
+/// release it whenever something else needs the
+/// mouse.
+fn grab_cursor(
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    mut grab: ResMut<CursorGrab>,
+    inspector: Option<Res<InspectorVisible>>,
+    settings: Option<Res<SettingsVisible>>,
+    time: Res<Time<Virtual>>,
+) {
+    let grabbed = window.focused
+        && !inspector.is_some_and(|visible| visible.0)
+        && !settings.is_some_and(|visible| visible.0)
+        && !time.is_paused();
+
+    if !grab.set_if_neq(CursorGrab { grabbed }) {
+        return;
+    }
+
+    if grabbed {
+        window.cursor_options.grab_mode =
+            CursorGrabMode::Locked;
+        window.cursor_options.visible = false;
+    } else {
+        window.cursor_options.grab_mode =
+            CursorGrabMode::None;
+        window.cursor_options.visible = true;
+    }
+}
@@ -1,20 +1,37 @@
 use avian3d::prelude::*;
-use bevy::{
-    input::common_conditions::input_toggle_active,
-    prelude::*,
-};
+use bevy::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use iyes_perf_ui::prelude::*;
 
+mod gizmos;
+
 pub struct DevPlugin;
 
 impl Plugin for DevPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(
-            WorldInspectorPlugin::default().run_if(
-                input_toggle_active(false, KeyCode::Escape),
-            ),
-        );
+        app.init_resource::<InspectorVisible>()
+            .init_resource::<DebugGizmosVisible>()
+            .add_plugins(
+                WorldInspectorPlugin::default()
+                    .run_if(inspector_visible),
+            )
+            .add_systems(
+                Update,
+                (toggle_inspector, toggle_debug_gizmos),
+            )
+            .add_systems(
+                Update,
+                (
+                    gizmos::draw_goals,
+                    gizmos::draw_targets,
+                    gizmos::draw_spawn_points,
+                    gizmos::draw_hold_points,
+                    gizmos::draw_pickup_casters,
+                    gizmos::draw_color_reveals,
+                    gizmos::draw_out_of_bounds,
+                )
+                    .run_if(debug_gizmos_visible),
+            );
         // .add_plugins(
         //     bevy::diagnostic::FrameTimeDiagnosticsPlugin,
         // )
@@ -24,6 +41,47 @@ impl Plugin for DevPlugin {
     }
 }
 
+/// Whether the world inspector is open. Toggled
+/// with Escape.
+#[derive(Resource, Default)]
+pub struct InspectorVisible(pub bool);
+
+pub fn inspector_visible(
+    visible: Res<InspectorVisible>,
+) -> bool {
+    visible.0
+}
+
+/// Whether level logic like goals, spawn points
+/// and platform paths is drawn with gizmos.
+/// Toggled with F3.
+#[derive(Resource, Default)]
+pub struct DebugGizmosVisible(pub bool);
+
+pub fn debug_gizmos_visible(
+    visible: Res<DebugGizmosVisible>,
+) -> bool {
+    visible.0
+}
+
+fn toggle_debug_gizmos(
+    input: Res<ButtonInput<KeyCode>>,
+    mut visible: ResMut<DebugGizmosVisible>,
+) {
+    if input.just_pressed(KeyCode::F3) {
+        visible.0 = !visible.0;
+    }
+}
+
+fn toggle_inspector(
+    input: Res<ButtonInput<KeyCode>>,
+    mut visible: ResMut<InspectorVisible>,
+) {
+    if input.just_pressed(KeyCode::Escape) {
+        visible.0 = !visible.0;
+    }
+}
+
 #[allow(dead_code)]
 fn spawn_debug_ui(mut commands: Commands) {
     // create a simple Perf UI with default settings
@@ -0,0 +1,190 @@
+use std::f32::consts::FRAC_PI_2;
+
+use avian3d::prelude::*;
+use bevy::{color::palettes::tailwind::*, prelude::*};
+
+use crate::{
+    Goal, HoldPoint, OutOfBoundsMarker, Player, Target,
+    level_spawn::SpawnPoint,
+    materials::uber::{ColorReveal, RevealColor},
+};
+
+fn draw_aabb(
+    gizmos: &mut Gizmos,
+    aabb: &ColliderAabb,
+    color: impl Into<Color>,
+) {
+    gizmos.cuboid(
+        Transform::from_translation(aabb.center())
+            .with_scale(aabb.size()),
+        color,
+    );
+}
+
+/// A flat ring on the ground at `position`.
+fn draw_ring(
+    gizmos: &mut Gizmos,
+    position: Vec3,
+    radius: f32,
+    color: impl Into<Color>,
+) {
+    gizmos.circle(
+        Isometry3d::new(
+            position,
+            Quat::from_rotation_x(FRAC_PI_2),
+        ),
+        radius,
+        color,
+    );
+}
+
+/// Goals turn green while a [`Target`] is inside
+/// them.
+pub(super) fn draw_goals(
+    mut gizmos: Gizmos,
+    goals: Query<
+        (&ColliderAabb, Option<&CollidingEntities>),
+        With<Goal>,
+    >,
+    targets: Query<(), With<Target>>,
+) {
+    for (aabb, colliding) in &goals {
+        let reached = colliding.is_some_and(|colliding| {
+            colliding
+                .iter()
+                .any(|entity| targets.contains(*entity))
+        });
+        let color =
+            if reached { GREEN_400 } else { AMBER_400 };
+        draw_aabb(&mut gizmos, aabb, color);
+    }
+}
+
+pub(super) fn draw_targets(
+    mut gizmos: Gizmos,
+    targets: Query<
+        (&GlobalTransform, Option<&ColliderAabb>),
+        With<Target>,
+    >,
+) {
+    for (transform, aabb) in &targets {
+        match aabb {
+            Some(aabb) => {
+                draw_aabb(&mut gizmos, aabb, SKY_400)
+            }
+            None => {
+                gizmos.sphere(
+                    Isometry3d::from_translation(
+                        transform.translation(),
+                    ),
+                    0.3,
+                    SKY_400,
+                );
+            }
+        }
+    }
+}
+
+/// Spawn points point the way players will face.
+pub(super) fn draw_spawn_points(
+    mut gizmos: Gizmos,
+    spawn_points: Query<&GlobalTransform, With<SpawnPoint>>,
+) {
+    for transform in &spawn_points {
+        let position = transform.translation();
+        draw_ring(&mut gizmos, position, 0.5, VIOLET_400);
+        gizmos.arrow(
+            position,
+            position + transform.forward() * 1.5,
+            VIOLET_400,
+        );
+    }
+}
+
+pub(super) fn draw_hold_points(
+    mut gizmos: Gizmos,
+    hold_points: Query<&GlobalTransform, With<HoldPoint>>,
+) {
+    for transform in &hold_points {
+        gizmos.sphere(
+            Isometry3d::from_translation(
+                transform.translation(),
+            ),
+            0.1,
+            PINK_400,
+        );
+    }
+}
+
+/// The box each player sweeps forward to find
+/// something to pick up, and what it found.
+pub(super) fn draw_pickup_casters(
+    mut gizmos: Gizmos,
+    players: Query<
+        (&ShapeCaster, &ShapeHits),
+        With<Player>,
+    >,
+) {
+    for (caster, hits) in &players {
+        let origin = caster.global_origin();
+        gizmos.cuboid(
+            Transform::from_translation(origin)
+                .with_scale(Vec3::splat(0.2)),
+            LIME_400,
+        );
+
+        match hits.iter().next() {
+            Some(hit) => {
+                gizmos.line(origin, hit.point1, LIME_400);
+                gizmos.sphere(
+                    Isometry3d::from_translation(
+                        hit.point1,
+                    ),
+                    0.1,
+                    LIME_400,
+                );
+            }
+            None => {
+                gizmos.line(
+                    origin,
+                    origin + caster.global_direction() * 2.,
+                    LIME_400.with_alpha(0.4),
+                );
+            }
+        }
+    }
+}
+
+/// The fully revealed radius, and a fainter
+/// sphere where the falloff ends.
+pub(super) fn draw_color_reveals(
+    mut gizmos: Gizmos,
+    reveals: Query<(&ColorReveal, &GlobalTransform)>,
+) {
+    for (reveal, transform) in &reveals {
+        let color = match reveal.color {
+            RevealColor::Red => RED_400,
+            RevealColor::Green => GREEN_400,
+            RevealColor::Blue => BLUE_400,
+        };
+        let isometry = Isometry3d::from_translation(
+            transform.translation(),
+        );
+        gizmos.sphere(isometry, reveal.radius, color);
+        // the furthest the falloff can spread
+        gizmos.sphere(
+            isometry,
+            reveal.reach(),
+            color.with_alpha(0.3),
+        );
+    }
+}
+
+pub(super) fn draw_out_of_bounds(
+    mut gizmos: Gizmos,
+    volumes: Query<&ColliderAabb, With<OutOfBoundsMarker>>,
+) {
+    for aabb in &volumes {
+        draw_aabb(&mut gizmos, aabb, RED_600);
+    }
+}
@@ -0,0 +1,202 @@
+use avian3d::prelude::{Physics, PhysicsTime};
+use bevy::{
+    input::mouse::AccumulatedMouseMotion, prelude::*,
+};
+use bevy_inspector_egui::bevy_egui::{EguiContexts, egui};
+
+use crate::{
+    PlayerIndex, camera::PlayerCamera,
+    post_process::PostProcessSettings,
+};
+
+/// Metres per second the free camera flies at.
+const FLY_SPEED: f32 = 8.;
+/// Multiplier applied to [`FLY_SPEED`] while
+/// holding shift.
+const FAST_MULTIPLIER: f32 = 4.;
+/// Radians of rotation per pixel of mouse
+/// movement.
+const LOOK_SENSITIVITY: f32 = 1. / 300.;
+
+pub struct FreeCameraPlugin;
+
+impl Plugin for FreeCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                toggle_free_camera.never_param_warn(),
+                (
+                    fly_free_camera,
+                    edit_post_process.never_param_warn(),
+                )
+                    .run_if(
+                        any_with_component::<FreeCamera>,
+                    ),
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Detaches player one's [`PlayerCamera`] from
+/// its [`CameraRig`](crate::camera::CameraRig)
+/// while the game is paused. Toggled with F2.
+#[derive(Component, Debug)]
+pub struct FreeCamera {
+    /// Where the orbit camera was when the free
+    /// camera took over.
+    restore: Transform,
+    /// Whether virtual time was already paused,
+    /// so leaving doesn't unpause it.
+    was_paused: bool,
+    /// The same for physics time.
+    physics_was_paused: bool,
+    yaw: f32,
+    pitch: f32,
+}
+
+fn toggle_free_camera(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    mut cameras: Query<
+        (
+            Entity,
+            &mut Transform,
+            Option<&FreeCamera>,
+            &PlayerIndex,
+        ),
+        With<PlayerCamera>,
+    >,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut physics_time: ResMut<Time<Physics>>,
+) {
+    if !input.just_pressed(KeyCode::F2) {
+        return;
+    }
+    // player one's view is the one that flies
+    let Some((entity, mut transform, free_camera, _)) =
+        cameras.iter_mut().find(|(.., index)| index.0 == 0)
+    else {
+        return;
+    };
+
+    match free_camera {
+        Some(free_camera) => {
+            *transform = free_camera.restore;
+            if !free_camera.was_paused {
+                virtual_time.unpause();
+            }
+            if !free_camera.physics_was_paused {
+                physics_time.unpause();
+            }
+            commands.entity(entity).remove::<FreeCamera>();
+        }
+        None => {
+            let (yaw, pitch, _) =
+                transform.rotation.to_euler(EulerRot::YXZ);
+            commands.entity(entity).insert(FreeCamera {
+                restore: *transform,
+                was_paused: virtual_time.is_paused(),
+                physics_was_paused: physics_time
+                    .is_paused(),
+                yaw,
+                pitch,
+            });
+            virtual_time.pause();
+            physics_time.pause();
+        }
+    }
+}
+
+/// Fly with WASD, Space/Ctrl for up/down, and
+/// look around while holding the right mouse
+/// button.
+fn fly_free_camera(
+    camera: Single<(&mut Transform, &mut FreeCamera)>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    motion: Res<AccumulatedMouseMotion>,
+    // virtual time is paused while flying
+    time: Res<Time<Real>>,
+) {
+    let (mut transform, mut free_camera) =
+        camera.into_inner();
+
+    // the cursor stays free for the settings
+    // window, so only look while dragging
+    if mouse.pressed(MouseButton::Right) {
+        free_camera.yaw -=
+            motion.delta.x * LOOK_SENSITIVITY;
+        free_camera.pitch = (free_camera.pitch
+            - motion.delta.y * LOOK_SENSITIVITY)
+            .clamp(-1.54, 1.54);
+    }
+    transform.rotation = Quat::from_euler(
+        EulerRot::YXZ,
+        free_camera.yaw,
+        free_camera.pitch,
+        0.,
+    );
+
+    let axis = |positive: KeyCode, negative: KeyCode| {
+        keys.pressed(positive) as i8 as f32
+            - keys.pressed(negative) as i8 as f32
+    };
+    let direction = transform.forward()
+        * axis(KeyCode::KeyW, KeyCode::KeyS)
+        + transform.right()
+            * axis(KeyCode::KeyD, KeyCode::KeyA)
+        + Vec3::Y
+            * axis(KeyCode::Space, KeyCode::ControlLeft);
+
+    let speed = if keys.pressed(KeyCode::ShiftLeft) {
+        FLY_SPEED * FAST_MULTIPLIER
+    } else {
+        FLY_SPEED
+    };
+    transform.translation += direction.normalize_or_zero()
+        * speed
+        * time.delta_secs();
+}
+
+/// Live controls for the outline post process
+/// while framing a shot.
+fn edit_post_process(
+    mut contexts: EguiContexts,
+    mut post_process: Single<
+        &mut PostProcessSettings,
+        With<FreeCamera>,
+    >,
+) {
+    egui::Window::new("Free camera").show(
+        contexts.ctx_mut(),
+        |ui| {
+            ui.label(
+                "WASD to fly, right mouse to look, F2 to return",
+            );
+            ui.add(
+                egui::Slider::new(
+                    &mut post_process.width,
+                    0..=16,
+                )
+                .text("outline width"),
+            );
+
+            let mut color =
+                post_process.stroke_color.to_f32_array();
+            ui.horizontal(|ui| {
+                ui.label("outline color");
+                if ui
+                    .color_edit_button_rgba_unmultiplied(
+                        &mut color,
+                    )
+                    .changed()
+                {
+                    post_process.stroke_color =
+                        LinearRgba::from_f32_array(color);
+                }
+            });
+        },
+    );
+}
@@ -0,0 +1,688 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use avian3d::prelude::{
+    Collider, ColliderConstructor,
+    ColliderConstructorHierarchy, RigidBody,
+};
+use bevy::{prelude::*, scene::SceneInstanceReady};
+
+use crate::{
+    GltfAssets, GoalEvent, Holding, Player,
+    coop::LocalPlayers,
+    level_spawn::{CurrentLevel, LevelState},
+    materials::UseGhostMaterial,
+    replay::{invalid_data, read_array},
+    settings::GhostSettings,
+};
+
+const GHOST_MAGIC: &[u8; 4] = b"BXGH";
+const GHOST_VERSION: u16 = 2;
+const GHOST_DIR: &str = "saves/ghosts";
+
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Ghost>()
+            .init_resource::<GhostRecorder>()
+            .init_resource::<BestGhost>()
+            .add_observer(on_add_player)
+            .add_observer(on_goal_event)
+            .add_observer(strip_ghost_collider)
+            .add_systems(
+                OnEnter(LevelState::Level),
+                load_best_ghost,
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    record_ghost_frame.never_param_warn(),
+                    advance_ghost,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    dress_ghost_held_box.never_param_warn(),
+                    pose_ghost.never_param_warn(),
+                ),
+            );
+    }
+}
+
+/// The translucent stand-in for the player's
+/// best run.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Ghost;
+
+/// The box the ghost is carrying, posed in world
+/// space.
+#[derive(Component, Default)]
+struct GhostHeldBox {
+    /// The pickup its meshes were copied from,
+    /// as an index into [`GhostRun::pickups`].
+    dressed_as: Option<u16>,
+}
+
+#[derive(Component)]
+struct GhostPlayback {
+    run: GhostRun,
+    /// Index of the frame the ghost is moving
+    /// towards.
+    tick: usize,
+}
+
+/// The pose of the player and their held box
+/// for one `FixedUpdate` tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GhostFrame {
+    pub player: Transform,
+    pub held: Option<GhostHeld>,
+}
+
+/// The box held during a [`GhostFrame`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GhostHeld {
+    /// Index into [`GhostRun::pickups`].
+    pub pickup: u16,
+    pub pose: Transform,
+}
+
+/// A completed run of a single level.
+#[derive(Debug, Clone, Default)]
+pub struct GhostRun {
+    /// Names of the pickups held during the run,
+    /// so the ghost carries the same boxes.
+    pub pickups: Vec<String>,
+    pub frames: Vec<GhostFrame>,
+}
+
+impl GhostRun {
+    /// The index of the pickup called `name`,
+    /// added to [`Self::pickups`] if it's new.
+    fn pickup_index(&mut self, name: &str) -> Option<u16> {
+        let index = self
+            .pickups
+            .iter()
+            .position(|pickup| pickup == name)
+            .unwrap_or_else(|| {
+                self.pickups.push(name.to_string());
+                self.pickups.len() - 1
+            });
+        u16::try_from(index).ok()
+    }
+
+    /// Where the best run for a level is saved.
+    pub fn path_for_level(level: &str) -> PathBuf {
+        Path::new(GHOST_DIR).join(format!("{level}.ghost"))
+    }
+
+    pub fn load(
+        path: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        Self::read_from(&mut fs::File::open(path)?)
+    }
+
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> io::Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::File::create(path)?;
+        self.write_to(&mut file)
+    }
+
+    /// Little-endian layout: magic, version,
+    /// pickup names, frame count, then each
+    /// frame's player pose and an optional held
+    /// pickup and its pose.
+    pub fn write_to(
+        &self,
+        writer: &mut impl Write,
+    ) -> io::Result<()> {
+        let pickup_count = u16::try_from(
+            self.pickups.len(),
+        )
+        .map_err(|_| invalid_data("too many pickups"))?;
+        let frame_count = u32::try_from(self.frames.len())
+            .map_err(|_| invalid_data("too many frames"))?;
+
+        writer.write_all(GHOST_MAGIC)?;
+        writer.write_all(&GHOST_VERSION.to_le_bytes())?;
+        writer.write_all(&pickup_count.to_le_bytes())?;
+        for pickup in &self.pickups {
+            let name_len = u16::try_from(pickup.len())
+                .map_err(|_| {
+                    invalid_data("pickup name too long")
+                })?;
+            writer.write_all(&name_len.to_le_bytes())?;
+            writer.write_all(pickup.as_bytes())?;
+        }
+        writer.write_all(&frame_count.to_le_bytes())?;
+
+        for frame in &self.frames {
+            write_pose(writer, &frame.player)?;
+            match &frame.held {
+                Some(held) => {
+                    writer.write_all(&[1])?;
+                    writer.write_all(
+                        &held.pickup.to_le_bytes(),
+                    )?;
+                    write_pose(writer, &held.pose)?;
+                }
+                None => writer.write_all(&[0])?,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from(
+        reader: &mut impl Read,
+    ) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != GHOST_MAGIC {
+            return Err(invalid_data("not a ghost file"));
+        }
+
+        let version =
+            u16::from_le_bytes(read_array(reader)?);
+        if version != GHOST_VERSION {
+            return Err(invalid_data(
+                "unsupported ghost version",
+            ));
+        }
+
+        let pickup_count =
+            u16::from_le_bytes(read_array(reader)?);
+        let pickups = (0..pickup_count)
+            .map(|_| -> io::Result<String> {
+                let name_len =
+                    u16::from_le_bytes(read_array(reader)?);
+                let mut name = vec![0; name_len as usize];
+                reader.read_exact(&mut name)?;
+                String::from_utf8(name).map_err(|_| {
+                    invalid_data("pickup name is not utf-8")
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let frame_count =
+            u32::from_le_bytes(read_array(reader)?);
+        let frames = (0..frame_count)
+            .map(|_| -> io::Result<GhostFrame> {
+                let player = read_pose(reader)?;
+                let [has_held] = read_array::<1>(reader)?;
+                let held = match has_held {
+                    0 => None,
+                    _ => {
+                        let pickup = u16::from_le_bytes(
+                            read_array(reader)?,
+                        );
+                        if usize::from(pickup)
+                            >= pickups.len()
+                        {
+                            return Err(invalid_data(
+                                "unknown pickup",
+                            ));
+                        }
+                        Some(GhostHeld {
+                            pickup,
+                            pose: read_pose(reader)?,
+                        })
+                    }
+                };
+                Ok(GhostFrame { player, held })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Self { pickups, frames })
+    }
+}
+
+fn write_pose(
+    writer: &mut impl Write,
+    transform: &Transform,
+) -> io::Result<()> {
+    for value in transform
+        .translation
+        .to_array()
+        .into_iter()
+        .chain(transform.rotation.to_array())
+    {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_pose(
+    reader: &mut impl Read,
+) -> io::Result<Transform> {
+    let mut values = [0.; 7];
+    for value in &mut values {
+        *value = f32::from_le_bytes(read_array(reader)?);
+    }
+    let [x, y, z, qx, qy, qz, qw] = values;
+
+    Ok(Transform::from_xyz(x, y, z)
+        .with_rotation(Quat::from_xyzw(qx, qy, qz, qw)))
+}
+
+/// The best run saved for the current level,
+/// read once when the level loads.
+#[derive(Resource, Default)]
+struct BestGhost(Option<GhostRun>);
+
+fn load_best_ghost(
+    mut best: ResMut<BestGhost>,
+    current_level: Res<CurrentLevel>,
+) {
+    best.0 = None;
+
+    let path = GhostRun::path_for_level(&current_level.0);
+    if !path.exists() {
+        return;
+    }
+    match GhostRun::load(&path) {
+        Ok(run) => best.0 = Some(run),
+        Err(error) => {
+            warn!(?error, ?path, "failed to load ghost");
+        }
+    }
+}
+
+/// The current attempt, recorded from the moment
+/// the player spawns.
+#[derive(Resource, Default)]
+struct GhostRecorder {
+    run: GhostRun,
+    recording: bool,
+}
+
+fn on_add_player(
+    _trigger: Trigger<OnAdd, Player>,
+    mut commands: Commands,
+    mut recorder: ResMut<GhostRecorder>,
+    best: Res<BestGhost>,
+    gltf_assets: Res<GltfAssets>,
+    gltfs: Res<Assets<Gltf>>,
+    local_players: Res<LocalPlayers>,
+) {
+    recorder.run = GhostRun::default();
+    // best runs are single player only
+    recorder.recording = local_players.0 == 1;
+    if !recorder.recording {
+        return;
+    }
+
+    let Some(run) = best.0.clone() else {
+        return;
+    };
+
+    let Some(character) =
+        gltfs.get(&gltf_assets.misc).and_then(|misc| {
+            misc.named_scenes.get("FirstCharacter")
+        })
+    else {
+        warn!("can't find player scene for ghost");
+        return;
+    };
+
+    commands
+        .spawn((
+            StateScoped(LevelState::Level),
+            Name::new("Ghost"),
+            SceneRoot(character.clone()),
+            Visibility::Hidden,
+            GhostPlayback { run, tick: 0 },
+            Ghost,
+        ))
+        .observe(on_ghost_scene_ready);
+
+    commands.spawn((
+        StateScoped(LevelState::Level),
+        Name::new("GhostHeldBox"),
+        Transform::default(),
+        Visibility::Hidden,
+        GhostHeldBox::default(),
+    ));
+}
+
+/// Give the ghost's held box the meshes of the
+/// pickup the best run is carrying, once the
+/// level has spawned it.
+fn dress_ghost_held_box(
+    mut commands: Commands,
+    ghost: Single<&GhostPlayback>,
+    held_box: Single<(Entity, &mut GhostHeldBox)>,
+    pickups: Query<(Entity, &Name, &GlobalTransform)>,
+    children: Query<&Children>,
+    meshes: Query<(&Mesh3d, &GlobalTransform)>,
+) {
+    let Some(held) = ghost
+        .run
+        .frames
+        .get(ghost.tick)
+        .and_then(|frame| frame.held)
+    else {
+        return;
+    };
+    let (held_box, mut dressed) = held_box.into_inner();
+    if dressed.dressed_as == Some(held.pickup) {
+        return;
+    }
+
+    let name = &ghost.run.pickups[usize::from(held.pickup)];
+    let Some((pickup, _, pickup_transform)) = pickups
+        .iter()
+        .find(|(_, other, _)| other.as_str() == name)
+    else {
+        return;
+    };
+
+    commands.entity(held_box).despawn_descendants();
+    for entity in std::iter::once(pickup)
+        .chain(children.iter_descendants(pickup))
+    {
+        let Ok((mesh, transform)) = meshes.get(entity)
+        else {
+            continue;
+        };
+        commands.entity(held_box).with_child((
+            mesh.clone(),
+            transform.reparented_to(pickup_transform),
+            UseGhostMaterial,
+        ));
+    }
+    dressed.dressed_as = Some(held.pickup);
+}
+
+/// Strip physics from the ghost's copy of the
+/// character scene and give it the ghost
+/// material.
+fn on_ghost_scene_ready(
+    trigger: Trigger<SceneInstanceReady>,
+    mut commands: Commands,
+    children: Query<&Children>,
+    meshes: Query<(), With<Mesh3d>>,
+) {
+    for entity in
+        children.iter_descendants(trigger.entity())
+    {
+        commands
+            .entity(entity)
+            .remove::<(RigidBody, Collider)>();
+
+        commands.entity(entity).remove::<(
+            ColliderConstructor,
+            ColliderConstructorHierarchy,
+        )>();
+
+        if meshes.get(entity).is_ok() {
+            commands
+                .entity(entity)
+                .insert(UseGhostMaterial);
+        }
+    }
+}
+
+/// Colliders can be built after the scene is
+/// ready, so catch any that land on the ghost.
+fn strip_ghost_collider(
+    trigger: Trigger<OnAdd, Collider>,
+    mut commands: Commands,
+    parents: Query<&Parent>,
+    ghosts: Query<(), With<Ghost>>,
+) {
+    let entity = trigger.entity();
+    if parents
+        .iter_ancestors(entity)
+        .any(|ancestor| ghosts.contains(ancestor))
+    {
+        commands
+            .entity(entity)
+            .remove::<(RigidBody, Collider)>();
+    }
+}
+
+fn on_goal_event(
+    _trigger: Trigger<GoalEvent>,
+    mut recorder: ResMut<GhostRecorder>,
+    mut best: ResMut<BestGhost>,
+    current_level: Res<CurrentLevel>,
+) {
+    if !recorder.recording {
+        return;
+    }
+    recorder.recording = false;
+
+    let best_ticks =
+        best.0.as_ref().map(|run| run.frames.len());
+    let ticks = recorder.run.frames.len();
+
+    if best_ticks.is_some_and(|best| best <= ticks) {
+        return;
+    }
+
+    let path = GhostRun::path_for_level(&current_level.0);
+    match recorder.run.save(&path) {
+        Ok(()) => {
+            info!(ticks, best = ?best_ticks, "new best run")
+        }
+        Err(error) => {
+            error!(?error, ?path, "failed to save ghost")
+        }
+    }
+    best.0 = Some(recorder.run.clone());
+}
+
+fn record_ghost_frame(
+    mut recorder: ResMut<GhostRecorder>,
+    player: Single<(&Transform, &Holding), With<Player>>,
+    pickups: Query<(&GlobalTransform, Option<&Name>)>,
+) {
+    if !recorder.recording {
+        return;
+    }
+    let (transform, holding) = player.into_inner();
+
+    let held = holding
+        .and_then(|entity| pickups.get(entity).ok())
+        .and_then(|(pose, name)| {
+            // unnamed pickups are recorded, but the
+            // ghost can't find them to copy later
+            let pickup = recorder.run.pickup_index(
+                name.map_or("", Name::as_str),
+            )?;
+            Some(GhostHeld {
+                pickup,
+                pose: pose.compute_transform(),
+            })
+        });
+
+    recorder.run.frames.push(GhostFrame {
+        player: *transform,
+        held,
+    });
+}
+
+fn advance_ghost(mut ghosts: Query<&mut GhostPlayback>) {
+    for mut playback in &mut ghosts {
+        if playback.tick < playback.run.frames.len() {
+            playback.tick += 1;
+        }
+    }
+}
+
+fn pose_ghost(
+    ghost: Single<
+        (&GhostPlayback, &mut Transform, &mut Visibility),
+        Without<GhostHeldBox>,
+    >,
+    held_box: Single<
+        (&mut Transform, &mut Visibility),
+        With<GhostHeldBox>,
+    >,
+    settings: Res<GhostSettings>,
+    time: Res<Time<Fixed>>,
+) {
+    let (playback, mut transform, mut visibility) =
+        ghost.into_inner();
+    let (mut box_transform, mut box_visibility) =
+        held_box.into_inner();
+
+    let frames = &playback.run.frames;
+    let (Some(previous), Some(next)) = (
+        frames.get(playback.tick.saturating_sub(1)),
+        frames.get(playback.tick),
+    ) else {
+        // the ghost has finished its run
+        *visibility = Visibility::Hidden;
+        *box_visibility = Visibility::Hidden;
+        return;
+    };
+
+    if !settings.enabled {
+        *visibility = Visibility::Hidden;
+        *box_visibility = Visibility::Hidden;
+        return;
+    }
+
+    // smooth out the fixed timestep between frames
+    let t = time.overstep_fraction();
+
+    *visibility = Visibility::Inherited;
+    *transform =
+        lerp_pose(&previous.player, &next.player, t);
+
+    match (previous.held, next.held) {
+        (Some(from), Some(to))
+            if from.pickup == to.pickup =>
+        {
+            *box_visibility = Visibility::Inherited;
+            *box_transform =
+                lerp_pose(&from.pose, &to.pose, t);
+        }
+        (_, Some(held)) => {
+            *box_visibility = Visibility::Inherited;
+            *box_transform = held.pose;
+        }
+        (_, None) => {
+            *box_visibility = Visibility::Hidden;
+        }
+    }
+}
+
+fn lerp_pose(
+    from: &Transform,
+    to: &Transform,
+    t: f32,
+) -> Transform {
+    Transform {
+        translation: from
+            .translation
+            .lerp(to.translation, t),
+        rotation: from.rotation.slerp(to.rotation, t),
+        scale: from.scale.lerp(to.scale, t),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run() -> GhostRun {
+        GhostRun {
+            pickups: vec!["Box.001".to_string()],
+            frames: vec![
+                GhostFrame {
+                    player: Transform::from_xyz(1., 2., 3.),
+                    held: None,
+                },
+                GhostFrame {
+                    player: Transform::from_xyz(
+                        1., 2.5, 3.,
+                    )
+                    .with_rotation(Quat::from_rotation_y(
+                        0.5,
+                    )),
+                    held: Some(GhostHeld {
+                        pickup: 0,
+                        pose: Transform::from_xyz(
+                            1., 3.5, 2.,
+                        ),
+                    }),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let run = run();
+        let mut bytes = vec![];
+        run.write_to(&mut bytes).unwrap();
+
+        let read =
+            GhostRun::read_from(&mut bytes.as_slice())
+                .unwrap();
+        assert_eq!(read.pickups, run.pickups);
+        assert_eq!(read.frames, run.frames);
+    }
+
+    #[test]
+    fn rejects_unknown_pickups() {
+        let mut run = run();
+        run.pickups.clear();
+        let mut bytes = vec![];
+        run.write_to(&mut bytes).unwrap();
+
+        let error =
+            GhostRun::read_from(&mut bytes.as_slice())
+                .unwrap_err();
+        assert_eq!(
+            error.kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut bytes = vec![];
+        run().write_to(&mut bytes).unwrap();
+        bytes[0] = b'X';
+
+        let error =
+            GhostRun::read_from(&mut bytes.as_slice())
+                .unwrap_err();
+        assert_eq!(
+            error.kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = vec![];
+        run().write_to(&mut bytes).unwrap();
+        bytes[4..6].copy_from_slice(
+            &(GHOST_VERSION + 1).to_le_bytes(),
+        );
+
+        let error =
+            GhostRun::read_from(&mut bytes.as_slice())
+                .unwrap_err();
+        assert_eq!(
+            error.kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
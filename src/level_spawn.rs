@@ -1,21 +1,27 @@
 use avian3d::prelude::{
     Collider, LockedAxes, RigidBody, ShapeCaster,
+    TransformInterpolation,
 };
 use bevy::{prelude::*, render::view::RenderLayers};
 use bevy_tnua::prelude::TnuaController;
 use bevy_tnua_avian3d::TnuaAvian3dSensorShape;
-use leafwing_input_manager::{
-    InputManagerBundle, prelude::*,
-};
+use leafwing_input_manager::InputManagerBundle;
 
 mod on_level_spawn;
 
 use crate::{
     AppState, GltfAssets, Holding, OriginalTransform,
-    OutOfBoundsBehavior, Player, camera::CameraRig,
-    controls::Action,
+    OutOfBoundsBehavior, Player, PlayerIndex,
+    camera::CameraRig,
+    controls::{
+        AbilityState, player_input_map, standing_collider,
+    },
 };
 
+/// Distance between co-op players sharing a
+/// spawn point.
+const PLAYER_SPACING: f32 = 1.5;
+
 pub struct PlayerSpawnPlugin;
 
 impl Plugin for PlayerSpawnPlugin {
@@ -60,7 +66,7 @@ pub enum LevelState {
 
 #[derive(Component, Reflect)]
 #[reflect(Component)]
-struct SpawnPoint;
+pub struct SpawnPoint;
 
 fn setup_level(
     mut commands: Commands,
@@ -105,8 +111,10 @@ fn on_spawn_player(
     gltf_assets: Res<GltfAssets>,
     gltfs: Res<Assets<Gltf>>,
     helper: TransformHelper,
-    mut camera_rig: Single<&mut CameraRig>,
+    mut camera_rigs: Query<(&mut CameraRig, &PlayerIndex)>,
 ) {
+    let index = trigger.player;
+
     let Ok(transform) = helper.compute_global_transform(
         trigger.spawn_point_entity,
     ) else {
@@ -114,28 +122,6 @@ fn on_spawn_player(
         return;
     };
 
-    let input_map = InputMap::new([
-        (Action::Jump, KeyCode::Space),
-        (Action::Interact, KeyCode::KeyE),
-    ])
-    .with_multiple([
-        (
-            Action::Interact,
-            GamepadButton::RightTrigger,
-        ),
-        (Action::Jump, GamepadButton::South),
-    ])
-    .with_dual_axis(Action::Move, VirtualDPad::wasd())
-    .with_dual_axis(
-        Action::Move,
-        GamepadStick::LEFT.with_deadzone_symmetric(0.1),
-    )
-    .with_dual_axis(Action::PanTilt, MouseMove::default())
-    .with_dual_axis(
-        Action::PanTilt,
-        GamepadStick::RIGHT.with_deadzone_symmetric(0.1),
-    );
-
     let Some(misc) = gltfs.get(&gltf_assets.misc) else {
         error!("no misc handle in gltfs");
         return;
@@ -149,9 +135,19 @@ fn on_spawn_player(
         // get the rotation of the spawn point empty
         // and store it in the camera_rig yaw so that the
         // player faces the right direction when spawned
-        camera_rig.yaw =
-            position.rotation.to_euler(EulerRot::XYZ).1;
-
+        if let Some((mut camera_rig, _)) = camera_rigs
+            .iter_mut()
+            .find(|(_, other)| **other == index)
+        {
+            camera_rig.yaw =
+                position.rotation.to_euler(EulerRot::XYZ).1;
+        }
+
+        // line co-op players up beside each other
+        position.translation += position.rotation
+            * Vec3::X
+            * PLAYER_SPACING
+            * index.0 as f32;
         position.translation.y += 10.;
 
         commands.spawn((
@@ -164,7 +160,10 @@ fn on_spawn_player(
                 // rigid body of the physics
                 // engine.
                 RigidBody::Dynamic,
-                Collider::capsule(0.5, 0.5),
+                standing_collider(),
+                // smooth out rendering between physics
+                // ticks, which the camera follows
+                TransformInterpolation,
                 // This bundle holds the main components.
                 TnuaController::default(),
                 // A sensor shape is not strictly
@@ -195,11 +194,15 @@ fn on_spawn_player(
                 // inputs into those
                 // actions
             ),
-            InputManagerBundle::with_map(input_map),
+            InputManagerBundle::with_map(player_input_map(
+                index,
+            )),
             OriginalTransform(position.into()),
             OutOfBoundsBehavior::Respawn,
             Holding(None),
+            AbilityState::default(),
             Player,
+            index,
         ));
     } else {
         warn!("can't find player scene in misc gltf");
@@ -209,4 +212,5 @@ fn on_spawn_player(
 #[derive(Event)]
 pub struct SpawnPlayerEvent {
     pub spawn_point_entity: Entity,
+    pub player: PlayerIndex,
 }
@@ -1,4 +1,7 @@
-use crate::level_spawn::SpawnPlayerEvent;
+use crate::{
+    PlayerIndex, coop::LocalPlayers,
+    level_spawn::SpawnPlayerEvent,
+};
 use bevy::{prelude::*, scene::SceneInstanceReady};
 
 use super::SpawnPoint;
@@ -7,10 +10,14 @@ pub fn on_level_spawn(
     _trigger: Trigger<SceneInstanceReady>,
     mut commands: Commands,
     spawn_points: Query<(Entity, &SpawnPoint)>,
+    local_players: Res<LocalPlayers>,
 ) {
     if let Ok((entity, _)) = spawn_points.get_single() {
-        commands.trigger(SpawnPlayerEvent {
-            spawn_point_entity: entity,
-        });
+        for index in 0..local_players.0 {
+            commands.trigger(SpawnPlayerEvent {
+                spawn_point_entity: entity,
+                player: PlayerIndex(index),
+            });
+        }
     }
 }
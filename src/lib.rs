@@ -1,11 +1,18 @@
 pub mod camera;
 pub mod controls;
+pub mod coop;
+pub mod cursor;
 pub mod dev;
+pub mod free_camera;
+pub mod ghost;
 pub mod level_spawn;
 pub mod materials;
 pub mod platforms;
 pub mod post_process;
+pub mod replay;
 pub mod section_texture;
+pub mod settings;
+pub mod switches;
 pub mod test_gltf_extras_components;
 
 use avian3d::prelude::{
@@ -29,8 +36,11 @@ impl Plugin for BoxesGamePlugin {
             .register_type::<Goal>()
             .register_type::<Player>()
             .register_type::<Target>()
+            // on a fixed tick, so replays reset at
+            // the same physics step they were
+            // recorded at
             .add_systems(
-                Update,
+                FixedUpdate,
                 (
                     respawn_important_stuff,
                     detect_goal_events,
@@ -86,6 +96,14 @@ pub struct Goal;
 #[reflect(Component)]
 pub struct Player;
 
+/// Which local player an entity belongs to. Each
+/// player shares theirs with their camera.
+#[derive(
+    Component, Reflect, Clone, Copy, PartialEq, Eq, Debug,
+)]
+#[reflect(Component)]
+pub struct PlayerIndex(pub usize);
+
 #[derive(
     Clone, Eq, PartialEq, Debug, Hash, Default, States,
 )]
@@ -144,8 +162,8 @@ fn respawn_important_stuff(
         Option<&OriginalTransform>,
     )>,
     mut commands: Commands,
-    players: Query<(), With<Player>>,
-    mut camera_rig: Option<Single<&mut CameraRig>>,
+    players: Query<&PlayerIndex, With<Player>>,
+    mut camera_rigs: Query<(&mut CameraRig, &PlayerIndex)>,
 ) {
     for Collision(contacts) in collision_event_reader.read()
     {
@@ -175,10 +193,17 @@ fn respawn_important_stuff(
                             transform.0.compute_transform(),
                         ));
 
-                        if players.get(*entity).is_ok() {
-                            if let Some(
-                                ref mut camera_rig,
-                            ) = camera_rig
+                        if let Ok(index) =
+                            players.get(*entity)
+                        {
+                            let camera_rig = camera_rigs
+                                .iter_mut()
+                                .find(|(_, other)| {
+                                    *other == index
+                                })
+                                .map(|(rig, _)| rig);
+                            if let Some(mut camera_rig) =
+                                camera_rig
                             {
                                 // get the rotation of the
                                 // spawn point empty
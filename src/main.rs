@@ -12,19 +12,24 @@ use bevy_15_game::{
     AppState, AudioAssets, BoxesGamePlugin, GltfAssets,
     HoldPoint, Holding, OutOfBoundsMarker, Player,
     TextureAssets,
-    camera::{CameraPlugin, PlayerCamera},
+    camera::CameraPlugin,
     controls::{Action, ControlsPlugin},
+    coop::CoopPlugin,
+    cursor::CursorPlugin,
     dev::DevPlugin,
+    free_camera::FreeCameraPlugin,
+    ghost::GhostPlugin,
     level_spawn::PlayerSpawnPlugin,
     materials::MaterialsPlugin,
     platforms::PlatformsPlugin,
-    post_process::{
-        PostProcessPlugin, PostProcessSettings,
-    },
+    post_process::PostProcessPlugin,
+    replay::ReplayPlugin,
     section_texture::{
         ATTRIBUTE_SECTION_COLOR, DrawSection,
-        SectionTexturePhasePlugin, SectionsPrepass,
+        SectionTexturePhasePlugin,
     },
+    settings::SettingsPlugin,
+    switches::{SwitchesPlugin, use_toggle_switches},
     test_gltf_extras_components::TestGltfExtrasComponentsPlugin,
     track_fake_long_task,
 };
@@ -71,7 +76,14 @@ fn main() {
             MaterialsPlugin,
             PlayerSpawnPlugin,
             PlatformsPlugin,
+            SettingsPlugin,
+            ReplayPlugin,
+            GhostPlugin,
+            CursorPlugin,
+            FreeCameraPlugin,
+            CoopPlugin,
         ))
+        .add_plugins(SwitchesPlugin)
         // Register DrawSection for all Mesh3ds
         .register_required_components::<Mesh3d, DrawSection>()
         .init_state::<AppState>()
@@ -84,15 +96,19 @@ fn main() {
         // gracefully quit the app when `AppState::Playing` is
         // reached
         .add_systems(OnEnter(AppState::Playing), setup)
+        // pickup, throw and toggle switches share
+        // `Action::Interact`,
+        // switches and throwing consume the press so
+        // the same tick doesn't immediately pick the
+        // item back up
         .add_systems(
             FixedUpdate,
-            throw_held_item.never_param_warn(),
-        )
-        .add_systems(
-            Update,
             (
+                throw_held_item.never_param_warn(),
                 raycast_player.never_param_warn(),
-            ),
+            )
+                .chain()
+                .after(use_toggle_switches),
         )
         // .add_systems(
         //         Update,
@@ -133,27 +149,9 @@ fn setup(
     gltf_assets: Res<GltfAssets>,
     gltfs: Res<Assets<Gltf>>,
 ) {
-    // spawn a camera to be able to see anything
+    // player cameras are spawned by `CoopPlugin`,
+    // one per local player
     // commands.spawn(Camera2d);
-    commands.spawn((
-        Camera3d::default(),
-        Transform::from_xyz(10., 15., 10.)
-            .looking_at(Vec3::new(0.0, 2., 0.0), Vec3::Y),
-        // OrderIndependentTransparencySettings::default(),
-        Camera {
-            hdr: true,
-            ..default()
-        },
-        // Msaa currently doesn't work with OIT
-        Msaa::Off,
-        PostProcessSettings {
-            stroke_color: Color::from(SLATE_950).into(),
-            width: 2,
-        },
-        SectionsPrepass,
-        // DepthPrepass,
-        PlayerCamera,
-    ));
 
     commands.spawn((
         DirectionalLight {
@@ -192,49 +190,65 @@ fn setup(
 
 fn raycast_player(
     mut commands: Commands,
-    query: Single<
-        (&ShapeCaster, &ShapeHits, &mut Holding),
-        With<Player>,
-    >,
-    action_state: Single<
-        &ActionState<Action>,
+    mut players: Query<
+        (
+            Entity,
+            &ShapeHits,
+            &mut Holding,
+            &ActionState<Action>,
+        ),
         With<Player>,
     >,
     mut transforms: Query<&mut Transform>,
-    named_entities: Query<(Entity, &Name)>,
+    names: Query<&Name>,
     children: Query<&Children>,
     hold_points: Query<(), With<HoldPoint>>,
     // collider_transforms: Query<&ColliderTransform>,
     // collider_info: Query<(&RigidBody, &Collider)>,
 ) {
-    if action_state.just_pressed(&Action::Interact) {
-        let (_, hits, mut holding) = query.into_inner();
+    // another player's box can't be taken out of
+    // their hands
+    let mut held = players
+        .iter()
+        .filter_map(|(_, _, holding, _)| holding.0)
+        .collect::<Vec<_>>();
+
+    for (player, hits, mut holding, action_state) in
+        &mut players
+    {
+        if !action_state.just_pressed(&Action::Interact) {
+            continue;
+        }
 
         if holding.is_some() {
             warn!("already holding something");
-            return;
+            continue;
         }
         // get empty entity that controls where player
         // holds objects
         // TODO: pull this out into scene spawning so that
         // we have direct access instead of needing to
         // find it
-        let Some(hold_empty) = named_entities
-            .iter()
-            .find_map(|(entity, name)| {
-                (name.as_str().starts_with("Hold"))
-                    .then_some(entity)
+        let Some(hold_empty) = children
+            .iter_descendants(player)
+            .find(|entity| {
+                names.get(*entity).is_ok_and(|name| {
+                    name.as_str().starts_with("Hold")
+                })
             })
         else {
             warn!("no entity with name `Hold`");
-            return;
+            continue;
         };
 
         // For the faster iterator that isn't sorted, use
         // `.iter()`
-        let Some(hit) = hits.iter().next() else {
+        let Some(hit) = hits
+            .iter()
+            .find(|hit| !held.contains(&hit.entity))
+        else {
             trace!("user interacted without a hit");
-            return;
+            continue;
         };
 
         // find hold_point empty on object that is being
@@ -252,7 +266,7 @@ fn raycast_player(
             warn!(
                 "no HoldPoint entity in Interactable entity tree"
             );
-            return;
+            continue;
         };
 
         // if we have a hold_point and an empty to parent
@@ -268,6 +282,7 @@ fn raycast_player(
             .insert(RigidBodyDisabled);
 
         **holding = Some(hit.entity);
+        held.push(hit.entity);
 
         let Ok(mut transform) =
             transforms.get_mut(hit.entity)
@@ -275,7 +290,7 @@ fn raycast_player(
             error!(
                 "interactable object must have transform"
             );
-            return;
+            continue;
         };
 
         // this is hardcoded to only a Y axis change
@@ -290,30 +305,33 @@ fn raycast_player(
 
 fn throw_held_item(
     mut commands: Commands,
-    query: Single<
+    mut players: Query<
         (
             &Transform,
             &mut Holding,
             &LinearVelocity,
+            &mut ActionState<Action>,
         ),
         With<Player>,
     >,
     global_transforms: Query<&GlobalTransform>,
-    action_state: Single<
-        &ActionState<Action>,
-        With<Player>,
-    >,
 ) {
-    if action_state.just_pressed(&Action::Interact) {
-        let (
-            transform,
-            mut holding,
-            player_linear_velocity,
-        ) = query.into_inner();
+    for (
+        transform,
+        mut holding,
+        player_linear_velocity,
+        mut action_state,
+    ) in &mut players
+    {
+        if !action_state.just_pressed(&Action::Interact) {
+            continue;
+        }
 
         if holding.is_none() {
-            warn!("not holding anything");
-            return;
+            // this press is a pickup, handled by
+            // `raycast_player`
+            trace!("not holding anything");
+            continue;
         }
 
         let entity = (**holding).expect("should have already checked to see if holding was full");
@@ -342,5 +360,6 @@ fn throw_held_item(
             ));
 
         **holding = None;
+        action_state.consume(&Action::Interact);
     }
 }
@@ -1,4 +1,6 @@
+pub mod decals;
 pub mod goal;
+pub mod paint;
 pub mod uber;
 
 use bevy::{
@@ -9,8 +11,11 @@ use bevy::{
     },
     prelude::*,
     render::storage::ShaderStorageBuffer,
+    utils::HashMap,
 };
+use decals::DecalPlugin;
 use goal::GoalMaterial;
+use paint::PaintPlugin;
 use uber::{UberMaterial, UberMaterialPlugin};
 
 use crate::section_texture::DrawSection;
@@ -21,19 +26,25 @@ impl Plugin for MaterialsPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<UseGoalMaterial>()
             .register_type::<UseUberMaterial>()
+            .register_type::<UseGhostMaterial>()
             .add_plugins((
                 UberMaterialPlugin,
+                PaintPlugin,
+                DecalPlugin,
                 MaterialPlugin::<GoalMaterial>::default(),
             ))
-            .add_systems(Startup, setup_materials);
+            .add_systems(Startup, setup_materials)
+            .add_systems(PostUpdate, apply_uber_materials);
     }
 }
 
 fn setup_materials(
     mut commands: Commands,
     mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+    mut images: ResMut<Assets<Image>>,
     asset_server: Res<AssetServer>,
     mut materials_goal: ResMut<Assets<GoalMaterial>>,
+    mut materials_std: ResMut<Assets<StandardMaterial>>,
 ) {
     commands.insert_resource(GoalMaterialStore(
         materials_goal.add(GoalMaterial {
@@ -45,21 +56,28 @@ fn setup_materials(
         }),
     ));
 
-    let sphere_data: Vec<[f32; 4]> = vec![];
-
-    let sdfs =
-        buffers.add(ShaderStorageBuffer::from(sphere_data));
+    commands.insert_resource(GhostMaterialStore(
+        materials_std.add(StandardMaterial {
+            base_color: Color::srgba(0.9, 0.95, 1.0, 0.3),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+    ));
 
-    let uber = UberMaterial {
-        sdfs,
-        decals: None,
-        grit: Some(
+    let uber = UberMaterial::new(
+        &mut buffers,
+        &mut images,
+        Some(
             asset_server
                 .load("textures/gritty_texture.png"),
         ),
-    };
+    );
 
-    commands.insert_resource(UberMaterialStore(uber));
+    commands.insert_resource(UberMaterialStore {
+        template: uber,
+        converted: default(),
+    });
 }
 #[derive(Resource)]
 struct GoalMaterialStore(Handle<GoalMaterial>);
@@ -90,54 +108,106 @@ fn on_add_use_goal_material(
 }
 
 #[derive(Resource)]
-struct UberMaterialStore(UberMaterial);
+struct GhostMaterialStore(Handle<StandardMaterial>);
 
+/// Render a mesh as a translucent ghost with no
+/// section outline.
 #[derive(Component, Reflect)]
 #[reflect(Component)]
-#[component(on_add = on_add_use_uber_material)]
-struct UseUberMaterial;
+#[component(on_add = on_add_use_ghost_material)]
+pub struct UseGhostMaterial;
 
-fn on_add_use_uber_material(
+fn on_add_use_ghost_material(
     mut world: DeferredWorld,
     entity: Entity,
     _: ComponentId,
 ) {
-    info!("on_add_use_uber_material");
-    let uber =
-        world.resource::<UberMaterialStore>().0.clone();
+    let ghost_material =
+        world.resource::<GhostMaterialStore>().0.clone();
 
-    let std_materials =
-        world.resource::<Assets<StandardMaterial>>();
-    let Some(mat_handle) = world
+    world
+        .commands()
         .entity(entity)
-        .get::<MeshMaterial3d<StandardMaterial>>(
-    ) else {
-        error!(
-            "can't find standard material on entity we are suppopsed to insert UberMaterial on"
-        );
-        return;
-    };
-
-    let std =
-        std_materials.get(&mat_handle.0).unwrap().clone();
-
-    let mut materials = world
-        .get_resource_mut::<Assets<
+        .remove::<MeshMaterial3d<
             ExtendedMaterial<
                 StandardMaterial,
                 UberMaterial,
             >,
         >>()
-        .unwrap();
+        .remove::<DrawSection>()
+        .insert((
+            MeshMaterial3d(ghost_material),
+            NotShadowReceiver,
+            NotShadowCaster,
+        ));
+}
 
-    let new_mat = materials.add(ExtendedMaterial {
-        base: std,
-        extension: uber,
-    });
+#[derive(Resource)]
+struct UberMaterialStore {
+    template: UberMaterial,
+    /// The uber material made from each standard
+    /// material so far, so entities sharing a
+    /// standard material keep sharing one.
+    converted: HashMap<
+        AssetId<StandardMaterial>,
+        Handle<
+            ExtendedMaterial<
+                StandardMaterial,
+                UberMaterial,
+            >,
+        >,
+    >,
+}
 
-    world
-        .commands()
-        .entity(entity)
-        .remove::<MeshMaterial3d<StandardMaterial>>()
-        .insert(MeshMaterial3d(new_mat));
+/// Swap a mesh's [`StandardMaterial`] for an
+/// [`UberMaterial`] built from it. Works on
+/// meshes from glTF extras and ones spawned in
+/// code alike.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct UseUberMaterial;
+
+/// Entities whose standard material is still
+/// loading are left for a later frame.
+fn apply_uber_materials(
+    mut commands: Commands,
+    mut store: ResMut<UberMaterialStore>,
+    std_materials: Res<Assets<StandardMaterial>>,
+    mut materials: ResMut<
+        Assets<
+            ExtendedMaterial<
+                StandardMaterial,
+                UberMaterial,
+            >,
+        >,
+    >,
+    entities: Query<
+        (Entity, &MeshMaterial3d<StandardMaterial>),
+        (With<UseUberMaterial>, Without<UseGhostMaterial>),
+    >,
+) {
+    for (entity, source) in &entities {
+        let id = source.id();
+        let uber = match store.converted.get(&id) {
+            Some(uber) => uber.clone(),
+            None => {
+                let Some(std) = std_materials.get(id)
+                else {
+                    continue;
+                };
+                let uber =
+                    materials.add(ExtendedMaterial {
+                        base: std.clone(),
+                        extension: store.template.clone(),
+                    });
+                store.converted.insert(id, uber.clone());
+                uber
+            }
+        };
+
+        commands
+            .entity(entity)
+            .remove::<MeshMaterial3d<StandardMaterial>>()
+            .insert(MeshMaterial3d(uber));
+    }
 }
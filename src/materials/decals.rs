@@ -0,0 +1,271 @@
+use bevy::{
+    asset::LoadState,
+    image::ImageSampler,
+    pbr::ExtendedMaterial,
+    prelude::*,
+    render::{
+        render_resource::ShaderType,
+        storage::ShaderStorageBuffer,
+    },
+    sprite::TextureAtlasBuilder,
+    transform::TransformSystem,
+    utils::HashMap,
+};
+use itertools::Itertools;
+
+use super::uber::{UberMaterial, touch_uber_materials};
+
+pub struct DecalPlugin;
+
+impl Plugin for DecalPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Decal>()
+            .register_type::<DecalBlend>()
+            .init_resource::<DecalAtlas>()
+            .add_systems(
+                PostUpdate,
+                (build_decal_atlas, pack_decal_buffer)
+                    .chain()
+                    .after(
+                        TransformSystem::TransformPropagate,
+                    ),
+            );
+    }
+}
+
+/// Projects `image` onto [`UberMaterial`]
+/// surfaces inside this entity's box, a unit
+/// cube scaled, turned and moved by its
+/// transform. The image covers the box's local
+/// XY plane and lands on surfaces facing its +Z
+/// axis.
+///
+/// Decals show in full color whether or not the
+/// surface under them has been revealed.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component, Default)]
+#[require(Transform)]
+pub struct Decal {
+    /// Asset path of the image, so decals can be
+    /// authored in glTF extras.
+    pub image: String,
+    pub blend: DecalBlend,
+    /// From `0` to `1`.
+    pub opacity: f32,
+}
+
+impl Default for Decal {
+    fn default() -> Self {
+        Self {
+            image: String::new(),
+            blend: DecalBlend::Alpha,
+            opacity: 1.,
+        }
+    }
+}
+
+/// How a [`Decal`] combines with the surface
+/// under it. The values match the `blend` switch
+/// in `uber.wgsl`.
+#[derive(Reflect, Debug, Clone, Copy, Default)]
+pub enum DecalBlend {
+    /// Painted over the surface by the image's
+    /// alpha.
+    #[default]
+    Alpha = 0,
+    /// Darkens the surface, for grime and
+    /// stencils.
+    Multiply = 1,
+    /// Brightens the surface, for glowing
+    /// markings.
+    Add = 2,
+}
+
+/// A [`Decal`] as `uber.wgsl` reads it.
+#[derive(ShaderType, Debug, Clone, Copy, Default)]
+pub struct GpuDecal {
+    pub world_to_decal: Mat4,
+    /// Where the decal's image sits in the atlas,
+    /// as `(min, max)` uvs.
+    pub uv_rect: Vec4,
+    pub blend: u32,
+    pub opacity: f32,
+}
+
+/// Every [`Decal`] image packed into the one
+/// texture [`UberMaterial::decals`] binds.
+#[derive(Resource, Debug, Default)]
+struct DecalAtlas {
+    images: HashMap<String, Handle<Image>>,
+    /// Uv rect of each image in the atlas, by
+    /// path.
+    rects: HashMap<String, Rect>,
+}
+
+/// Load new decal images and, once they're all
+/// in, rebuild the atlas with them. The atlas is
+/// written into each material's existing
+/// `decals` image, so every material sharing it
+/// picks it up.
+fn build_decal_atlas(
+    mut atlas: ResMut<DecalAtlas>,
+    decals: Query<&Decal>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    materials: Res<
+        Assets<
+            ExtendedMaterial<
+                StandardMaterial,
+                UberMaterial,
+            >,
+        >,
+    >,
+) {
+    let needed = decals
+        .iter()
+        .map(|decal| &decal.image)
+        .filter(|path| !path.is_empty())
+        .unique()
+        .filter(|path| !atlas.rects.contains_key(*path))
+        .cloned()
+        .collect::<Vec<_>>();
+    if needed.is_empty() {
+        return;
+    }
+    for path in &needed {
+        if !atlas.images.contains_key(path) {
+            let handle = asset_server.load(path);
+            atlas.images.insert(path.clone(), handle);
+        }
+    }
+    let loading = needed.iter().any(|path| {
+        let handle = &atlas.images[path];
+        !images.contains(handle)
+            && !matches!(
+                asset_server.load_state(handle),
+                LoadState::Failed(_)
+            )
+    });
+    if loading {
+        return;
+    }
+
+    let mut builder = TextureAtlasBuilder::default();
+    // keeps filtering from bleeding neighbours in
+    builder.padding(UVec2::splat(2));
+    let mut packed = vec![];
+    for (path, handle) in &atlas.images {
+        if let Some(image) = images.get(handle) {
+            builder.add_texture(Some(handle.id()), image);
+            packed.push((path.clone(), handle.id()));
+        }
+    }
+    let (layout, sources, mut image) = match builder.build()
+    {
+        Ok(built) => built,
+        Err(error) => {
+            error!(?error, "unable to build decal atlas");
+            // don't try the same images again
+            // every frame
+            for path in needed {
+                atlas.rects.insert(path, Rect::EMPTY);
+            }
+            return;
+        }
+    };
+    image.sampler = ImageSampler::linear();
+
+    let size = layout.size.as_vec2();
+    atlas.rects = packed
+        .into_iter()
+        .filter_map(|(path, id)| {
+            let rect = layout.textures
+                [sources.texture_index(id)?];
+            Some((
+                path,
+                Rect::from_corners(
+                    rect.min.as_vec2() / size,
+                    rect.max.as_vec2() / size,
+                ),
+            ))
+        })
+        .collect();
+    // images that failed to load have no rect,
+    // mark them so they aren't waited on again
+    for path in needed {
+        atlas.rects.entry(path).or_insert(Rect::EMPTY);
+    }
+
+    for handle in materials
+        .iter()
+        .filter_map(|(_, mat)| mat.extension.decals.clone())
+        .unique()
+    {
+        if let Some(target) = images.get_mut(&handle) {
+            *target = image.clone();
+        }
+    }
+}
+
+/// Upload every [`Decal`] whenever one of them
+/// or the atlas changes.
+fn pack_decal_buffer(
+    mut materials: ResMut<
+        Assets<
+            ExtendedMaterial<
+                StandardMaterial,
+                UberMaterial,
+            >,
+        >,
+    >,
+    mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+    atlas: Res<DecalAtlas>,
+    decals: Query<(&Decal, &GlobalTransform)>,
+    changed: Query<
+        (),
+        (
+            With<Decal>,
+            Or<(Changed<Decal>, Changed<GlobalTransform>)>,
+        ),
+    >,
+    mut removed: RemovedComponents<Decal>,
+) {
+    let removed = removed.read().count() > 0;
+    if !atlas.is_changed() && changed.is_empty() && !removed
+    {
+        return;
+    }
+
+    let mut packed = decals
+        .iter()
+        .filter_map(|(decal, transform)| {
+            let rect = atlas.rects.get(&decal.image)?;
+            (!rect.is_empty()).then(|| GpuDecal {
+                world_to_decal: transform
+                    .compute_matrix()
+                    .inverse(),
+                uv_rect: Vec4::new(
+                    rect.min.x, rect.min.y, rect.max.x,
+                    rect.max.y,
+                ),
+                blend: decal.blend as u32,
+                opacity: decal.opacity.clamp(0., 1.),
+            })
+        })
+        .collect::<Vec<_>>();
+    if packed.is_empty() {
+        // storage buffers can't be empty, and a
+        // zero opacity decal draws nothing
+        packed.push(GpuDecal::default());
+    }
+
+    for handle in
+        touch_uber_materials(&mut materials, |uber| {
+            uber.decal_buffer.clone()
+        })
+    {
+        if let Some(buffer) = buffers.get_mut(&handle) {
+            buffer.set_data(packed.as_slice());
+        }
+    }
+}
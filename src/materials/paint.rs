@@ -0,0 +1,509 @@
+use avian3d::prelude::{
+    Collider, ColliderAabb, Position, Rotation,
+};
+use bevy::{
+    image::ImageSampler,
+    pbr::ExtendedMaterial,
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{
+            Extent3d, ShaderType, TextureDimension,
+            TextureFormat,
+        },
+        storage::ShaderStorageBuffer,
+    },
+    transform::TransformSystem,
+};
+
+use super::uber::{
+    ColorReveal, RevealColor, RevealFalloff, UberMaterial,
+    touch_uber_materials,
+};
+
+pub struct PaintPlugin;
+
+impl Plugin for PaintPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<PaintReveal>()
+            .register_type::<PaintVolume>()
+            .register_type::<PaintProgress>()
+            .init_resource::<PaintMap>()
+            .add_systems(
+                PostUpdate,
+                (
+                    fit_paint_map,
+                    paint_reveals,
+                    update_paint_progress,
+                    upload_paint_map,
+                )
+                    .chain()
+                    .after(
+                        TransformSystem::TransformPropagate,
+                    ),
+            );
+    }
+}
+
+/// Seconds between uploads of the [`PaintMap`],
+/// since each one invalidates every
+/// [`UberMaterial`].
+const PAINT_UPLOAD_INTERVAL: f32 = 0.1;
+
+/// Most cells along any side of the
+/// [`PaintMap`]. Cells grow past
+/// [`PaintVolume::cell_size`] to stay under it.
+const MAX_PAINT_MAP_CELLS: u32 = 128;
+
+/// How far a cell has to be painted before
+/// [`PaintMap::revealed_fraction`] counts it.
+const PAINTED_THRESHOLD: u8 = 128;
+
+/// Makes a [`ColorReveal`] leave its color behind
+/// in the [`PaintMap`] wherever it goes.
+#[derive(Component, Reflect, Debug, Default)]
+#[reflect(Component, Default)]
+#[require(ColorReveal)]
+pub struct PaintReveal;
+
+/// The box, centered on this entity, that
+/// [`PaintReveal`]s can paint in. Without one
+/// nothing is painted.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component, Default)]
+pub struct PaintVolume {
+    pub half_size: Vec3,
+    /// Side of one cell of the [`PaintMap`].
+    pub cell_size: f32,
+}
+
+impl Default for PaintVolume {
+    fn default() -> Self {
+        Self {
+            half_size: Vec3::new(32., 8., 32.),
+            cell_size: 0.5,
+        }
+    }
+}
+
+/// Keeps `fraction` up to date with how much of
+/// this entity's collider surface has been
+/// painted, for painting puzzles. The collider is
+/// expected to stay put.
+#[derive(Component, Reflect, Debug, Default)]
+#[reflect(Component, Default)]
+pub struct PaintProgress {
+    /// Only count paint of this color. `None`
+    /// counts any.
+    pub color: Option<RevealColor>,
+    /// From `0` to `1`.
+    pub fraction: f32,
+    /// The [`PaintMap::surface_cells`] of the
+    /// collider, and the map generation they were
+    /// found in.
+    #[reflect(ignore)]
+    surface: Option<(u32, Vec<usize>)>,
+}
+
+/// Paint left behind by [`PaintReveal`]s: a grid
+/// over the [`PaintVolume`] with one channel per
+/// [`RevealColor`], sampled by `uber.wgsl` as a
+/// 3d texture.
+#[derive(Resource, Debug, Default)]
+pub struct PaintMap {
+    origin: Vec3,
+    cell_size: f32,
+    size: UVec3,
+    /// Red, green and blue paint per cell, `x`
+    /// first, then `y`, then `z`.
+    cells: Vec<[u8; 4]>,
+    dirty: bool,
+    /// Bumped every time the map is refitted, so
+    /// cell indices from an older map aren't
+    /// used with it.
+    generation: u32,
+}
+
+impl PaintMap {
+    fn new(
+        center: Vec3,
+        half_size: Vec3,
+        cell_size: f32,
+    ) -> Self {
+        let extent = half_size.abs() * 2.;
+        let cell_size = cell_size.max(0.01).max(
+            extent.max_element()
+                / MAX_PAINT_MAP_CELLS as f32,
+        );
+        let size =
+            (extent / cell_size).ceil().as_uvec3().clamp(
+                UVec3::ONE,
+                UVec3::splat(MAX_PAINT_MAP_CELLS),
+            );
+        Self {
+            origin: center - half_size.abs(),
+            cell_size,
+            size,
+            cells: vec![
+                [0; 4];
+                size.element_product() as usize
+            ],
+            dirty: true,
+            generation: 0,
+        }
+    }
+
+    fn extent(&self) -> Vec3 {
+        self.size.as_vec3() * self.cell_size
+    }
+
+    /// The cells overlapping the box from `min`
+    /// to `max`, or `None` if it's outside the
+    /// map.
+    fn cells_in(
+        &self,
+        min: Vec3,
+        max: Vec3,
+    ) -> Option<(UVec3, UVec3)> {
+        if self.cells.is_empty() {
+            return None;
+        }
+        let first = ((min - self.origin) / self.cell_size)
+            .floor()
+            .max(Vec3::ZERO);
+        let last = ((max - self.origin) / self.cell_size)
+            .floor()
+            .min((self.size - 1).as_vec3());
+        first
+            .cmple(last)
+            .all()
+            .then(|| (first.as_uvec3(), last.as_uvec3()))
+    }
+
+    /// Every cell from `first` to `last`,
+    /// inclusive.
+    fn cells_between(
+        first: UVec3,
+        last: UVec3,
+    ) -> impl Iterator<Item = UVec3> {
+        (first.z..=last.z).flat_map(move |z| {
+            (first.y..=last.y).flat_map(move |y| {
+                (first.x..=last.x)
+                    .map(move |x| UVec3::new(x, y, z))
+            })
+        })
+    }
+
+    fn index(&self, cell: UVec3) -> usize {
+        ((cell.z * self.size.y + cell.y) * self.size.x
+            + cell.x) as usize
+    }
+
+    fn cell_center(&self, cell: UVec3) -> Vec3 {
+        self.origin
+            + (cell.as_vec3() + 0.5) * self.cell_size
+    }
+
+    /// Paint `reveal` into every cell it reaches
+    /// from `position`.
+    fn paint(
+        &mut self,
+        reveal: &ColorReveal,
+        position: Vec3,
+    ) {
+        // grit can't be sampled here, so it paints
+        // as a linear falloff
+        let reach =
+            reveal.radius + reveal.falloff_width.max(0.);
+        let Some((first, last)) = self
+            .cells_in(position - reach, position + reach)
+        else {
+            return;
+        };
+        let channel = reveal.color as usize - 1;
+        let strength = reveal.strength.clamp(0., 1.);
+
+        for cell in Self::cells_between(first, last) {
+            let edge =
+                (self.cell_center(cell).distance(position)
+                    - reveal.radius)
+                    .max(0.)
+                    / reveal.falloff_width.max(0.0001);
+            let amount = match reveal.falloff {
+                RevealFalloff::Hard => {
+                    if edge <= 0. {
+                        1.
+                    } else {
+                        0.
+                    }
+                }
+                RevealFalloff::Smooth => {
+                    let t = edge.clamp(0., 1.);
+                    1. - t * t * (3. - 2. * t)
+                }
+                RevealFalloff::Linear
+                | RevealFalloff::Grit => {
+                    1. - edge.clamp(0., 1.)
+                }
+            };
+            let value =
+                (amount * strength * 255.).round() as u8;
+
+            let index = self.index(cell);
+            if value > self.cells[index][channel] {
+                self.cells[index][channel] = value;
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// The cells `collider`'s surface passes
+    /// through, rather than every cell of its
+    /// bounds, most of which are empty air or the
+    /// collider's inside.
+    pub fn surface_cells(
+        &self,
+        collider: &Collider,
+        position: Position,
+        rotation: Rotation,
+        aabb: &ColliderAabb,
+    ) -> Vec<usize> {
+        let Some((first, last)) =
+            self.cells_in(aabb.min, aabb.max)
+        else {
+            return vec![];
+        };
+        // a surface through any part of a cell
+        // passes within this of its center
+        let reach = self.cell_size * 3f32.sqrt() / 2.;
+
+        Self::cells_between(first, last)
+            .filter(|cell| {
+                let center = self.cell_center(*cell);
+                let (closest, _) = collider.project_point(
+                    position, rotation, center, false,
+                );
+                closest.distance(center) <= reach
+            })
+            .map(|cell| self.index(cell))
+            .collect()
+    }
+
+    /// The fraction of `cells`, from
+    /// [`PaintMap::surface_cells`], that have
+    /// been painted, either in `color` or in
+    /// any color.
+    pub fn revealed_fraction(
+        &self,
+        cells: &[usize],
+        color: Option<RevealColor>,
+    ) -> f32 {
+        if cells.is_empty() {
+            return 0.;
+        }
+        let painted = cells
+            .iter()
+            .filter_map(|index| self.cells.get(*index))
+            .filter(|cell| {
+                let value = match color {
+                    Some(color) => cell[color as usize - 1],
+                    None => {
+                        cell[0].max(cell[1]).max(cell[2])
+                    }
+                };
+                value >= PAINTED_THRESHOLD
+            })
+            .count();
+        painted as f32 / cells.len() as f32
+    }
+
+    /// The map as a 3d texture for `uber.wgsl`.
+    /// An empty map is a single blank cell.
+    pub(super) fn to_image(&self) -> Image {
+        let (size, data) = if self.cells.is_empty() {
+            (UVec3::ONE, vec![0; 4])
+        } else {
+            (self.size, self.cells.as_flattened().to_vec())
+        };
+        let mut image = Image::new(
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: size.z,
+            },
+            TextureDimension::D3,
+            data,
+            TextureFormat::Rgba8Unorm,
+            // kept in the main world too, so
+            // `upload_paint_map` can write into it
+            RenderAssetUsages::default(),
+        );
+        image.sampler = ImageSampler::linear();
+        image
+    }
+
+    pub(super) fn to_gpu(&self) -> GpuPaintBounds {
+        if self.cells.is_empty() {
+            return GpuPaintBounds::default();
+        }
+        GpuPaintBounds {
+            origin: self.origin,
+            extent: self.extent(),
+        }
+    }
+}
+
+/// Where `uber.wgsl` maps the [`PaintMap`]
+/// texture into the world. A zero `extent` means
+/// there's no paint.
+#[derive(ShaderType, Debug, Clone, Copy, Default)]
+pub struct GpuPaintBounds {
+    pub origin: Vec3,
+    pub extent: Vec3,
+}
+
+/// Start a fresh map whenever a [`PaintVolume`]
+/// appears or moves, and drop it once the last
+/// one is gone.
+fn fit_paint_map(
+    mut map: ResMut<PaintMap>,
+    volumes: Query<
+        (&PaintVolume, &GlobalTransform),
+        Or<(
+            Changed<PaintVolume>,
+            Changed<GlobalTransform>,
+        )>,
+    >,
+    mut removed: RemovedComponents<PaintVolume>,
+    remaining: Query<(), With<PaintVolume>>,
+) {
+    let generation = map.generation.wrapping_add(1);
+    if removed.read().count() > 0 && remaining.is_empty() {
+        *map = PaintMap {
+            dirty: true,
+            generation,
+            ..default()
+        };
+    }
+
+    if let Some((volume, transform)) = volumes.iter().next()
+    {
+        *map = PaintMap {
+            generation,
+            ..PaintMap::new(
+                transform.translation(),
+                volume.half_size,
+                volume.cell_size,
+            )
+        };
+    }
+}
+
+fn paint_reveals(
+    mut map: ResMut<PaintMap>,
+    brushes: Query<
+        (&ColorReveal, &GlobalTransform),
+        With<PaintReveal>,
+    >,
+) {
+    for (reveal, transform) in &brushes {
+        map.paint(reveal, transform.translation());
+    }
+}
+
+/// Surfaces find their cells once per map, then
+/// recount them whenever there's new paint.
+fn update_paint_progress(
+    map: Res<PaintMap>,
+    mut surfaces: Query<(
+        &mut PaintProgress,
+        &Collider,
+        &ColliderAabb,
+        &Position,
+        &Rotation,
+    )>,
+) {
+    for (
+        mut progress,
+        collider,
+        aabb,
+        position,
+        rotation,
+    ) in &mut surfaces
+    {
+        let seeded = progress.surface.as_ref().is_some_and(
+            |(generation, _)| *generation == map.generation,
+        );
+        if !seeded {
+            let cells = map.surface_cells(
+                collider, *position, *rotation, aabb,
+            );
+            progress.surface =
+                Some((map.generation, cells));
+        } else if !map.dirty {
+            continue;
+        }
+
+        let fraction = progress.surface.as_ref().map_or(
+            0.,
+            |(_, cells)| {
+                map.revealed_fraction(cells, progress.color)
+            },
+        );
+        if progress.fraction != fraction {
+            progress.fraction = fraction;
+        }
+    }
+}
+
+fn upload_paint_map(
+    mut map: ResMut<PaintMap>,
+    mut materials: ResMut<
+        Assets<
+            ExtendedMaterial<
+                StandardMaterial,
+                UberMaterial,
+            >,
+        >,
+    >,
+    mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+    mut images: ResMut<Assets<Image>>,
+    time: Res<Time>,
+    mut since_upload: Local<f32>,
+) {
+    *since_upload += time.delta_secs();
+    if !map.dirty || *since_upload < PAINT_UPLOAD_INTERVAL {
+        return;
+    }
+    *since_upload = 0.;
+    map.dirty = false;
+
+    let bounds = map.to_gpu();
+    for (paint, paint_bounds) in
+        touch_uber_materials(&mut materials, |uber| {
+            (uber.paint.clone(), uber.paint_bounds.clone())
+        })
+    {
+        if let Some(image) = images.get_mut(&paint) {
+            // only a refit changes the map's size,
+            // otherwise the paint is copied in place
+            let size = image.texture_descriptor.size;
+            let data = map.cells.as_flattened();
+            if UVec3::new(
+                size.width,
+                size.height,
+                size.depth_or_array_layers,
+            ) == map.size
+                && image.data.len() == data.len()
+            {
+                image.data.copy_from_slice(data);
+            } else {
+                *image = map.to_image();
+            }
+        }
+        if let Some(buffer) = buffers.get_mut(&paint_bounds)
+        {
+            buffer.set_data(bounds);
+        }
+    }
+}
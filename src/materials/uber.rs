@@ -1,18 +1,30 @@
+use std::hash::Hash;
+
 use bevy::{
     pbr::{ExtendedMaterial, MaterialExtension},
     prelude::*,
     render::{
-        render_resource::{AsBindGroup, ShaderRef},
+        primitives::{Frustum, Sphere},
+        render_resource::{
+            AsBindGroup, ShaderRef, ShaderType,
+        },
         storage::ShaderStorageBuffer,
     },
 };
 use itertools::Itertools;
 
+use super::{
+    decals::GpuDecal,
+    paint::{GpuPaintBounds, PaintMap},
+};
+
 pub struct UberMaterialPlugin;
 
 impl Plugin for UberMaterialPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<ColorReveal>()
+            .register_type::<RevealColor>()
+            .register_type::<RevealFalloff>()
             .add_plugins(MaterialPlugin::<
                 ExtendedMaterial<
                     StandardMaterial,
@@ -23,14 +35,225 @@ impl Plugin for UberMaterialPlugin {
     }
 }
 
-#[derive(Component, Reflect)]
-#[reflect(Component)]
-pub enum ColorReveal {
+/// Brings back the color of [`UberMaterial`]
+/// surfaces near it, which are otherwise grey.
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component, Default)]
+pub struct ColorReveal {
+    pub color: RevealColor,
+    /// Everything within this distance is fully
+    /// revealed.
+    pub radius: f32,
+    /// How the reveal fades out past `radius`.
+    pub falloff: RevealFalloff,
+    /// Distance past `radius` the fade takes.
+    pub falloff_width: f32,
+    /// How much of the color comes back, from `0`
+    /// to `1`.
+    pub strength: f32,
+    /// When there are more reveals in view than
+    /// [`MAX_COLOR_REVEALS`], higher priorities
+    /// are kept first, then the ones closest to a
+    /// camera.
+    pub priority: i32,
+}
+
+impl Default for ColorReveal {
+    fn default() -> Self {
+        Self {
+            color: RevealColor::Red,
+            radius: 3.,
+            falloff: RevealFalloff::Grit,
+            falloff_width: 1. / 3.,
+            strength: 1.,
+            priority: 0,
+        }
+    }
+}
+
+impl ColorReveal {
+    /// The furthest this reveal shows at all.
+    /// Grit can stretch the falloff up to ten
+    /// times, matching the `max(grit, 0.1)` in
+    /// `uber.wgsl`.
+    pub fn reach(&self) -> f32 {
+        let stretch = match self.falloff {
+            RevealFalloff::Grit => 10.,
+            _ => 1.,
+        };
+        self.radius + self.falloff_width.max(0.) * stretch
+    }
+
+    fn to_gpu(&self, position: Vec3) -> GpuColorReveal {
+        GpuColorReveal {
+            position,
+            radius: self.radius,
+            color: self.color as u32,
+            falloff: self.falloff as u32,
+            falloff_width: self.falloff_width,
+            strength: self.strength,
+        }
+    }
+}
+
+#[derive(Reflect, Debug, Clone, Copy, Default)]
+pub enum RevealColor {
+    #[default]
     Red = 1,
     Green = 2,
     Blue = 3,
 }
 
+/// The shape of a [`ColorReveal`]'s edge. The
+/// values match the `falloff` switch in
+/// `uber.wgsl`.
+#[derive(Reflect, Debug, Clone, Copy, Default)]
+pub enum RevealFalloff {
+    /// No fade at all.
+    Hard = 0,
+    Linear = 1,
+    Smooth = 2,
+    /// Broken up by the material's grit texture.
+    #[default]
+    Grit = 3,
+}
+
+/// A [`ColorReveal`] as `uber.wgsl` reads it.
+#[derive(ShaderType, Debug, Clone, Copy, Default)]
+pub struct GpuColorReveal {
+    pub position: Vec3,
+    pub radius: f32,
+    pub color: u32,
+    pub falloff: u32,
+    pub falloff_width: f32,
+    pub strength: f32,
+}
+
+/// Most [`ColorReveal`]s sent to the GPU at once.
+pub const MAX_COLOR_REVEALS: usize = 128;
+
+/// Smallest side of a [`GpuRevealGrid`] cell.
+const REVEAL_GRID_CELL_SIZE: f32 = 4.;
+
+/// Most cells along either side of a
+/// [`GpuRevealGrid`]. Cells grow past
+/// [`REVEAL_GRID_CELL_SIZE`] to stay under it.
+const MAX_REVEAL_GRID_CELLS: u32 = 64;
+
+/// A coarse grid over the ground plane, so each
+/// fragment only checks the reveals that can
+/// reach it. Each cell is a `(start, count)`
+/// range of `reveal_indices`.
+#[derive(ShaderType, Debug, Clone, Default)]
+pub struct GpuRevealGrid {
+    pub origin: Vec2,
+    pub cell_size: f32,
+    pub columns: u32,
+    pub rows: u32,
+    #[size(runtime)]
+    pub cells: Vec<UVec2>,
+}
+
+impl GpuRevealGrid {
+    /// Bucket `reveals` into cells by the square
+    /// each one reaches across, returning the
+    /// grid and the index list its cells
+    /// point into.
+    fn build(
+        reveals: &[(GpuColorReveal, f32)],
+    ) -> (Self, Vec<u32>) {
+        if reveals.is_empty() {
+            // storage buffers can't be empty
+            return (
+                Self {
+                    cell_size: 1.,
+                    columns: 1,
+                    rows: 1,
+                    cells: vec![UVec2::ZERO],
+                    ..default()
+                },
+                vec![0],
+            );
+        }
+
+        let (min, max) = reveals.iter().fold(
+            (Vec2::MAX, Vec2::MIN),
+            |(min, max), &(reveal, reach)| {
+                let center = reveal.position.xz();
+                (
+                    min.min(center - reach),
+                    max.max(center + reach),
+                )
+            },
+        );
+        let extent = max - min;
+        let cell_size = REVEAL_GRID_CELL_SIZE.max(
+            extent.max_element()
+                / MAX_REVEAL_GRID_CELLS as f32,
+        );
+        let dimensions =
+            (extent / cell_size).ceil().as_uvec2().clamp(
+                UVec2::ONE,
+                UVec2::splat(MAX_REVEAL_GRID_CELLS),
+            );
+
+        let cell_count =
+            dimensions.element_product() as usize;
+        let mut buckets = vec![vec![]; cell_count];
+        for (index, &(reveal, reach)) in
+            reveals.iter().enumerate()
+        {
+            let center = reveal.position.xz();
+            let cell = |point: Vec2| {
+                ((point - min) / cell_size)
+                    .floor()
+                    .as_uvec2()
+                    .min(dimensions - 1)
+            };
+            let first = cell(center - reach);
+            let last = cell(center + reach);
+            for row in first.y..=last.y {
+                for column in first.x..=last.x {
+                    buckets[(row * dimensions.x + column)
+                        as usize]
+                        .push(index as u32);
+                }
+            }
+        }
+
+        let mut indices = vec![];
+        let cells = buckets
+            .into_iter()
+            .map(|bucket| {
+                let start = indices.len() as u32;
+                indices.extend(bucket);
+                UVec2::new(
+                    start,
+                    indices.len() as u32 - start,
+                )
+            })
+            .collect();
+        if indices.is_empty() {
+            indices.push(0);
+        }
+
+        (
+            Self {
+                origin: min,
+                cell_size,
+                columns: dimensions.x,
+                rows: dimensions.y,
+                cells,
+            },
+            indices,
+        )
+    }
+}
+
+/// Upload the reveals any camera can see, but
+/// only when that selection or one of its reveals
+/// has changed, since every upload has to
+/// invalidate every [`UberMaterial`].
 fn pack_color_reveal_buffer(
     mut materials: ResMut<
         Assets<
@@ -41,57 +264,220 @@ fn pack_color_reveal_buffer(
         >,
     >,
     mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
-    sdf_locations: Query<(&ColorReveal, &GlobalTransform)>,
+    reveals: Query<(
+        Entity,
+        &ColorReveal,
+        &GlobalTransform,
+    )>,
+    changed: Query<
+        (),
+        (
+            With<ColorReveal>,
+            Or<(
+                Changed<ColorReveal>,
+                Changed<GlobalTransform>,
+            )>,
+        ),
+    >,
+    cameras: Query<(&Frustum, &GlobalTransform, &Camera)>,
+    mut uploaded: Local<Vec<Entity>>,
 ) {
-    let new_sdfs = sdf_locations
+    let cameras = cameras
         .iter()
-        .map(|(color, transform)| {
-            let pos = transform.translation();
-            [
-                pos.x,
-                pos.y,
-                pos.z,
-                match color {
-                    ColorReveal::Red => 1.,
-                    ColorReveal::Green => 2.,
-                    ColorReveal::Blue => 3.,
-                },
-            ]
+        .filter(|(_, _, camera)| camera.is_active)
+        .map(|(frustum, transform, _)| {
+            (frustum, transform.translation())
         })
         .collect::<Vec<_>>();
 
-    for buffer_handle in materials
-        // This is a load-bearing iter_mut in bevy 0.15.
-        // Without it, the material handle isn't
-        // invalidated, thus the buffer isn't
-        // updated even though the data has changed
-        .iter_mut()
-        .map(|(_, mat)| &mat.extension.sdfs)
-        .unique()
+    let mut visible = reveals
+        .iter()
+        .filter_map(|(entity, reveal, transform)| {
+            let position = transform.translation();
+            let bounds = Sphere {
+                center: position.into(),
+                radius: reveal.reach(),
+            };
+            cameras
+                .iter()
+                .filter(|(frustum, _)| {
+                    frustum.intersects_sphere(&bounds, true)
+                })
+                .map(|(_, camera)| {
+                    camera.distance(position)
+                })
+                .min_by(f32::total_cmp)
+                .map(|distance| {
+                    (entity, reveal, position, distance)
+                })
+        })
+        .collect::<Vec<_>>();
+    visible.sort_by(|a, b| {
+        b.1.priority
+            .cmp(&a.1.priority)
+            .then(a.3.total_cmp(&b.3))
+    });
+    visible.truncate(MAX_COLOR_REVEALS);
+    // distance only picks which reveals make the
+    // cut, so reveals swapping places as the
+    // camera moves don't count as a new selection
+    visible.sort_by_key(|(entity, ..)| *entity);
+
+    let selected = visible
+        .iter()
+        .map(|(entity, ..)| *entity)
+        .collect::<Vec<_>>();
+    if selected == *uploaded
+        && !selected
+            .iter()
+            .any(|entity| changed.contains(*entity))
+    {
+        return;
+    }
+    *uploaded = selected;
+
+    let packed = visible
+        .iter()
+        .map(|(_, reveal, position, _)| {
+            (reveal.to_gpu(*position), reveal.reach())
+        })
+        .collect::<Vec<_>>();
+    let (grid, indices) = GpuRevealGrid::build(&packed);
+    let mut new_sdfs = packed
+        .into_iter()
+        .map(|(reveal, _)| reveal)
+        .collect::<Vec<_>>();
+    if new_sdfs.is_empty() {
+        new_sdfs.push(GpuColorReveal::default());
+    }
+
+    for (sdfs, reveal_grid, reveal_indices) in
+        touch_uber_materials(&mut materials, |uber| {
+            (
+                uber.sdfs.clone(),
+                uber.reveal_grid.clone(),
+                uber.reveal_indices.clone(),
+            )
+        })
     {
-        let Some(buffer) = buffers.get_mut(buffer_handle)
-        else {
-            warn!("unable to access storage buffer on uber material");
+        if ![&sdfs, &reveal_grid, &reveal_indices]
+            .into_iter()
+            .all(|handle| buffers.contains(handle))
+        {
+            warn!(
+                "unable to access storage buffer on uber material"
+            );
             continue;
-        };
-        buffer.set_data(new_sdfs.as_slice());
+        }
+        if let Some(buffer) = buffers.get_mut(&sdfs) {
+            buffer.set_data(new_sdfs.as_slice());
+        }
+        if let Some(buffer) = buffers.get_mut(&reveal_grid)
+        {
+            buffer.set_data(grid.clone());
+        }
+        if let Some(buffer) =
+            buffers.get_mut(&reveal_indices)
+        {
+            buffer.set_data(indices.as_slice());
+        }
     }
 }
 
+/// The distinct `handles` of every
+/// [`UberMaterial`], to write new data into.
+///
+/// This is a load-bearing `iter_mut` in bevy
+/// 0.15. Without it, the materials aren't
+/// invalidated, so their bind groups keep the old
+/// buffers and images even though the data has
+/// changed.
+pub(super) fn touch_uber_materials<T: Eq + Hash + Clone>(
+    materials: &mut Assets<
+        ExtendedMaterial<StandardMaterial, UberMaterial>,
+    >,
+    handles: impl Fn(&UberMaterial) -> T,
+) -> Vec<T> {
+    materials
+        .iter_mut()
+        .map(|(_, mat)| handles(&mat.extension))
+        .unique()
+        .collect()
+}
+
 #[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
 pub struct UberMaterial {
     // We need to ensure that the bindings of the base
     // material and the extension do not conflict,
     // so we start from binding slot 100, leaving slots
     // 0-99 for the base material.
+    /// Every [`ColorReveal`], packed as
+    /// [`GpuColorReveal`]s.
     #[storage(100, read_only)]
     pub sdfs: Handle<ShaderStorageBuffer>,
+    /// Every [`Decal`](super::decals::Decal)
+    /// image, packed into one atlas.
     #[texture(101)]
     #[sampler(102)]
     pub decals: Option<Handle<Image>>,
     #[texture(103)]
     #[sampler(104)]
     pub grit: Option<Handle<Image>>,
+    /// A [`GpuRevealGrid`] over `sdfs`.
+    #[storage(105, read_only)]
+    pub reveal_grid: Handle<ShaderStorageBuffer>,
+    /// The `sdfs` indices the grid's cells point
+    /// into.
+    #[storage(106, read_only)]
+    pub reveal_indices: Handle<ShaderStorageBuffer>,
+    /// Where `paint` sits in the world, as a
+    /// [`GpuPaintBounds`].
+    #[storage(107, read_only)]
+    pub paint_bounds: Handle<ShaderStorageBuffer>,
+    /// The [`PaintMap`](super::paint::PaintMap).
+    #[texture(108, dimension = "3d")]
+    #[sampler(109)]
+    pub paint: Handle<Image>,
+    /// Every [`Decal`](super::decals::Decal),
+    /// packed as [`GpuDecal`]s.
+    #[storage(110, read_only)]
+    pub decal_buffer: Handle<ShaderStorageBuffer>,
+}
+
+impl UberMaterial {
+    /// A material with empty reveal, paint and
+    /// decal buffers, which are filled in as
+    /// [`ColorReveal`]s and decals appear.
+    pub fn new(
+        buffers: &mut Assets<ShaderStorageBuffer>,
+        images: &mut Assets<Image>,
+        grit: Option<Handle<Image>>,
+    ) -> Self {
+        let (grid, indices) = GpuRevealGrid::build(&[]);
+        let paint = PaintMap::default();
+        Self {
+            sdfs: buffers.add(ShaderStorageBuffer::from(
+                vec![GpuColorReveal::default()],
+            )),
+            // replaced by the atlas once there are
+            // decals
+            decals: Some(images.add(Image::default())),
+            grit,
+            reveal_grid: buffers
+                .add(ShaderStorageBuffer::from(grid)),
+            reveal_indices: buffers
+                .add(ShaderStorageBuffer::from(indices)),
+            paint_bounds: buffers.add(
+                ShaderStorageBuffer::from(paint.to_gpu()),
+            ),
+            paint: images.add(paint.to_image()),
+            decal_buffer: buffers.add(
+                ShaderStorageBuffer::from(vec![
+                    GpuDecal::default(),
+                ]),
+            ),
+        }
+    }
 }
 
 impl MaterialExtension for UberMaterial {
@@ -1,18 +1,33 @@
-use std::{
-    any::TypeId,
-    f32::consts::{FRAC_PI_2, PI},
-};
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, TAU};
 
-use avian3d::prelude::Rotation;
+use avian3d::prelude::*;
 use bevy::{
     animation::{
-        AnimationEntityMut, AnimationEvaluationError,
         AnimationTarget, AnimationTargetId, animated_field,
     },
     prelude::*,
+    transform::TransformSystem,
+    utils::HashMap,
+};
+use clock::{
+    PlatformClip, PlatformClock, PlatformSync,
+    PlatformSyncGroup,
+};
+use path::{
+    PathCurve, PathMode, PathWaypoint, PlatformPath,
+    Waypoint,
 };
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    GltfAssets, dev::debug_gizmos_visible,
+    level_spawn::LevelState,
+    switches::update_switch_platforms,
+};
+
+pub mod clock;
+pub mod path;
+
 pub struct PlatformsPlugin;
 
 impl Plugin for PlatformsPlugin {
@@ -21,12 +36,61 @@ impl Plugin for PlatformsPlugin {
             .register_type::<PlatformBehavior>()
             .register_type::<PlatformAnimationOffset>()
             .register_type::<AnimationOffsetTimer>()
+            .register_type::<PathWaypoint>()
+            .register_type::<PlatformPose>()
+            .register_type::<ClipPose>()
+            .register_type::<Rotate>()
+            .register_type::<RotationType>()
+            .register_type::<PlatformClock>()
+            .register_type::<PlatformSync>()
+            .register_type::<PlatformSyncGroup>()
+            .init_resource::<PlatformClock>()
+            .init_resource::<PlatformAnimations>()
+            .add_systems(
+                OnEnter(LevelState::Level),
+                clock::reset_platform_clock,
+            )
+            .add_systems(
+                OnExit(LevelState::Level),
+                clear_platform_animations,
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    clock::tick_platform_clock.run_if(
+                        in_state(LevelState::Level),
+                    ),
+                    (
+                        clock::sync_platforms_to_clock,
+                        clock::advance_switch_platforms
+                            .after(update_switch_platforms),
+                    ),
+                    // pose platforms for this step's
+                    // time rather than the last frame's
+                    bevy::animation::animate_targets,
+                    apply_clip_poses,
+                    drive_kinematic_platforms,
+                )
+                    .chain(),
+            )
             .add_systems(
                 Update,
                 (
-                    tick_animation_offset_timer,
                     setup_animation_platforms,
+                    (
+                        path::draw_platform_paths,
+                        clock::draw_platform_phases,
+                    )
+                        .run_if(debug_gizmos_visible),
                 ),
+            )
+            .add_systems(
+                PostUpdate,
+                apply_part_poses
+                    .after(bevy::animation::Animation)
+                    .before(
+                        TransformSystem::TransformPropagate,
+                    ),
             );
     }
 }
@@ -37,10 +101,34 @@ pub enum PlatformBehavior {
     #[default]
     Rotate90X,
     Rotate90Y,
+    /// Rotate by `angle` radians around an
+    /// arbitrary `axis`.
+    RotateAxis {
+        axis: Vec3,
+        angle: f32,
+    },
     MoveLinear {
         start: Vec3,
         end: Vec3,
     },
+    /// Travel through a list of waypoints. With
+    /// no `waypoints` listed, the platform's
+    /// child empties with a [`PathWaypoint`]
+    /// are used.
+    Path {
+        waypoints: Vec<Waypoint>,
+        mode: PathMode,
+    },
+    /// Play the animation called `name` from the
+    /// level's glTF at `speed` times its authored
+    /// speed. The platform has to be animated in
+    /// the clip, and animated children come along
+    /// with it.
+    Clip {
+        name: String,
+        speed: f32,
+        looping: bool,
+    },
 }
 
 #[derive(
@@ -55,230 +143,806 @@ pub enum StartEnd {
 #[reflect(Component)]
 pub struct Platform;
 
+/// Seconds this platform runs ahead of the
+/// [`PlatformClock`].
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct PlatformAnimationOffset(pub f32);
 
-#[derive(Component, Reflect)]
+/// Spins a platform around `axis` forever, in the
+/// way its [`RotationType`] describes. Takes the
+/// place of a [`PlatformBehavior`].
+#[derive(Component, Reflect, Debug)]
 #[reflect(Component)]
-struct Rotate {
-    axis: Vec2,
+#[require(RotationType)]
+pub struct Rotate {
+    pub axis: Vec3,
 }
 
-#[derive(Component, Reflect)]
+#[derive(Component, Reflect, Debug)]
 #[reflect(Component)]
-enum RotationType {
-    Stepped { step_count: u32 },
-    Continuous { speed: i32 },
+pub enum RotationType {
+    /// Turn a full circle in `step_count` equal
+    /// steps of one second each, waiting `pause`
+    /// seconds after every step.
+    Stepped { step_count: u32, pause: f32 },
+    /// Turn at a steady `speed` in degrees per
+    /// second. Negative speeds turn the other
+    /// way.
+    Continuous { speed: f32 },
+}
+
+impl Default for RotationType {
+    fn default() -> Self {
+        Self::Continuous { speed: 45. }
+    }
 }
 
 #[derive(Component)]
 struct Processed;
 
+/// Where a platform's animation wants it to be,
+/// in its parent's space. Platforms are kinematic
+/// bodies, so rather than teleporting them the
+/// animation writes here and
+/// `drive_kinematic_platforms` moves them with
+/// velocities, which lets friction and Tnua carry
+/// whatever is standing on them.
+/// Animated parts of a platform are posed
+/// directly instead.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct PlatformPose {
+    pub translation: Vec3,
+    /// Applied on top of `rest_rotation`, so
+    /// platforms placed at different angles can
+    /// share a rotation curve.
+    pub rotation: Quat,
+    /// The rotation the platform was placed with.
+    pub rest_rotation: Quat,
+}
+
+impl PlatformPose {
+    fn at_rest(transform: &Transform) -> Self {
+        Self {
+            translation: transform.translation,
+            rotation: Quat::IDENTITY,
+            rest_rotation: transform.rotation,
+        }
+    }
+
+    fn transform(&self) -> Transform {
+        Transform::from_translation(self.translation)
+            .with_rotation(
+                self.rotation * self.rest_rotation,
+            )
+    }
+}
+
+/// A pose that turns further than this in a
+/// single fixed step is a jump, like a rotation
+/// clip restarting, and is snapped to instead of
+/// spun towards.
+const PLATFORM_SNAP_ANGLE: f32 = FRAC_PI_4;
+
+/// Generated platform animations, shared between
+/// the platforms of a level that would build
+/// identical ones. Cleared when the level ends.
+#[derive(Resource, Default)]
+struct PlatformAnimations(
+    HashMap<String, CachedPlatformAnimation>,
+);
+
+struct CachedPlatformAnimation {
+    graph: Handle<AnimationGraph>,
+    animation: AnimationNodeIndex,
+}
+
+/// The target id of `entity`, from the names on
+/// the way down from its `platform`, like glTF
+/// animation targets. Ids only need to be unique
+/// per player, so the platform itself is always
+/// `Platform`, and unnamed parts go by their
+/// place among their siblings. That way
+/// identical platforms end up with identical
+/// ids, and can share animations.
+fn platform_target_id(
+    platform: Entity,
+    entity: Entity,
+    parents: &Query<&Parent>,
+    children: &Query<&Children>,
+    names: &Query<&Name>,
+) -> AnimationTargetId {
+    let mut path = vec![];
+    let mut current = entity;
+    while current != platform {
+        let Ok(parent) = parents.get(current) else {
+            break;
+        };
+        let name = names
+            .get(current)
+            .cloned()
+            .unwrap_or_else(|_| {
+                let index = children
+                    .get(parent.get())
+                    .ok()
+                    .and_then(|siblings| {
+                        siblings.iter().position(|child| {
+                            *child == current
+                        })
+                    })
+                    .unwrap_or_default();
+                Name::new(format!("#{index}"))
+            });
+        path.push(name);
+        current = parent.get();
+    }
+    path.push(Name::new("Platform"));
+    path.reverse();
+    AnimationTargetId::from_names(path.iter())
+}
+
+fn clear_platform_animations(
+    mut cache: ResMut<PlatformAnimations>,
+) {
+    cache.0.clear();
+}
+
 // fn rotate_platforms(query: Query<>) {
 
 // }
+/// Build one animation per platform, covering the
+/// platform itself and any descendants with their
+/// own behavior, so parts like gears can move
+/// independently of the platform carrying them.
 fn setup_animation_platforms(
-    query: Query<
-        (Entity, &PlatformBehavior),
-        (With<Platform>, Without<Processed>),
+    platforms: Query<
+        Entity,
+        (
+            With<Platform>,
+            Or<(With<PlatformBehavior>, With<Rotate>)>,
+            Without<Processed>,
+        ),
     >,
+    animated: Query<
+        (
+            Option<&PlatformBehavior>,
+            Option<(&Rotate, &RotationType)>,
+        ),
+        Or<(With<PlatformBehavior>, With<Rotate>)>,
+    >,
+    nested_platforms: Query<(), With<Platform>>,
     mut commands: Commands,
     mut animations: ResMut<Assets<AnimationClip>>,
     mut graphs: ResMut<Assets<AnimationGraph>>,
-    timers: Query<&AnimationOffsetTimer>,
+    mut cache: ResMut<PlatformAnimations>,
+    transforms: Query<&Transform>,
+    children: Query<&Children>,
+    parents: Query<&Parent>,
+    names: Query<&Name>,
+    path_waypoints: Query<(&PathWaypoint, &Transform)>,
+    mut animation_targets: Query<&mut AnimationTarget>,
+    gltf_assets: Res<GltfAssets>,
+    gltfs: Res<Assets<Gltf>>,
 ) {
-    for (entity, behavior) in &query {
-        match behavior {
-            PlatformBehavior::Rotate90X => {
-                let platform_target_id =
-                    AnimationTargetId::from_name(
-                        &Name::new("Platform"),
-                    );
+    for platform in &platforms {
+        commands.entity(platform).insert(Processed);
+
+        if let Ok((
+            Some(PlatformBehavior::Clip {
+                name,
+                speed,
+                looping,
+            }),
+            _,
+        )) = animated.get(platform)
+        {
+            let Some(misc) = gltfs.get(&gltf_assets.misc)
+            else {
+                error!("no misc handle in gltfs");
+                continue;
+            };
+            setup_clip_platform(
+                platform,
+                name,
+                *speed,
+                *looping,
+                misc,
+                transforms
+                    .get(platform)
+                    .copied()
+                    .unwrap_or_default(),
+                &mut commands,
+                &animations,
+                &mut graphs,
+                &mut cache,
+                &children,
+                &mut animation_targets,
+            );
+            continue;
+        }
 
-                let mut animation =
-                    AnimationClip::default();
+        let mut animation = AnimationClip::default();
+        let mut repeat = true;
+        let mut key = String::new();
+        let mut targets = vec![];
+
+        let parts = children
+            .iter_descendants(platform)
+            .filter(|entity| {
+                !nested_platforms.contains(*entity)
+            });
+        for entity in std::iter::once(platform).chain(parts)
+        {
+            let Ok((behavior, spin)) = animated.get(entity)
+            else {
+                continue;
+            };
+            let transform = transforms
+                .get(entity)
+                .copied()
+                .unwrap_or_default();
+            let target = platform_target_id(
+                platform, entity, &parents, &children,
+                &names,
+            );
 
-                let rotation_curve = EasingCurve::new(
-                    Quat::IDENTITY,
-                    Quat::from_rotation_z(FRAC_PI_2),
-                    EaseFunction::ElasticInOut,
-                )
-                .reparametrize_linear(interval(0.0, 4.0).unwrap())
-                .expect("this curve has bounded domain, so this should never fail");
-
-                animation.add_curve_to_target(
-                    platform_target_id,
-                    AnimatableCurve::new(
-                        // animated_field!(
-                        //     Transform::rotation
-                        // )
-                        RotationProperty,
-                        rotation_curve,
-                    ),
-                );
+            // the curves only bake in what's keyed
+            // here, the rest of the transform is
+            // left to the pose
+            key.push_str(&format!(
+                "{target:?}{behavior:?}{spin:?}"
+            ));
+            let Some(repeats) = add_platform_curves(
+                &mut animation,
+                &mut key,
+                target,
+                entity,
+                &transform,
+                behavior,
+                spin,
+                &mut commands,
+                &children,
+                &parents,
+                &transforms,
+                &path_waypoints,
+            ) else {
+                continue;
+            };
+            key.push(';');
+            repeat &= repeats;
+            targets.push((entity, target, transform));
+        }
+
+        if targets.is_empty() {
+            continue;
+        }
 
-                let (graph, animation_index) =
+        let period = animation.duration();
+        let cached =
+            cache.0.entry(key).or_insert_with(|| {
+                let (graph, animation) =
                     AnimationGraph::from_clip(
                         animations.add(animation),
                     );
+                CachedPlatformAnimation {
+                    graph: graphs.add(graph),
+                    animation,
+                }
+            });
+
+        // the animation is paused because its time
+        // comes from the platform clock, or from a
+        // switch
+        let mut player = AnimationPlayer::default();
+        let active = player.play(cached.animation);
+        active.pause();
+        if repeat {
+            active.repeat();
+        }
 
-                // Create the animation player, and set it
-                // to repeat
-                let mut player = AnimationPlayer::default();
+        commands.entity(platform).insert((
+            RigidBody::Kinematic,
+            PlatformClip::new(
+                cached.animation,
+                period,
+                repeat,
+                1.,
+            ),
+            AnimationGraphHandle(cached.graph.clone()),
+            player,
+        ));
+        for (entity, target, transform) in targets {
+            commands.entity(entity).insert((
+                PlatformPose::at_rest(&transform),
+                AnimationTarget {
+                    id: target,
+                    player: platform,
+                },
+            ));
+        }
+    }
+}
 
-                // then play now
-                player.play(animation_index).repeat();
+/// Play the glTF animation `name` on `platform`.
+/// The clip's curves for the platform itself are
+/// handed to a [`ClipPose`] child, so they pose
+/// the platform instead of teleporting it, and
+/// its animated descendants are moved over to the
+/// platform's player.
+fn setup_clip_platform(
+    platform: Entity,
+    name: &str,
+    speed: f32,
+    looping: bool,
+    gltf: &Gltf,
+    transform: Transform,
+    commands: &mut Commands,
+    animations: &Assets<AnimationClip>,
+    graphs: &mut Assets<AnimationGraph>,
+    cache: &mut PlatformAnimations,
+    children: &Query<&Children>,
+    animation_targets: &mut Query<&mut AnimationTarget>,
+) {
+    let Some(clip) = gltf.named_animations.get(name) else {
+        warn!(
+            ?platform,
+            name,
+            "no animation with this name"
+        );
+        return;
+    };
+    let Ok(target) = animation_targets
+        .get(platform)
+        .map(|target| target.id)
+    else {
+        warn!(
+            ?platform,
+            name,
+            "clip platforms need to be animated in the glTF"
+        );
+        return;
+    };
+    let period = animations
+        .get(clip)
+        .map_or(0., |clip| clip.duration());
+    if period <= 0. || !speed.is_finite() || speed <= 0. {
+        warn!(
+            ?platform,
+            name,
+            speed,
+            "clip platforms need a clip with some length and a positive speed"
+        );
+        return;
+    }
 
-                // if the entity doesn't have an offset
-                // adjustment timer
-                if timers.get(entity).is_ok() {
-                    player.pause_all();
-                }
+    for entity in children.iter_descendants(platform) {
+        if let Ok(mut target) =
+            animation_targets.get_mut(entity)
+        {
+            target.player = platform;
+        }
+    }
 
-                commands.entity(entity).insert((
-                    Processed,
-                    AnimationGraphHandle(graphs.add(graph)),
-                    player,
-                    AnimationTarget {
-                        id: platform_target_id,
-                        player: entity,
-                    },
-                ));
+    let cached = cache
+        .0
+        .entry(format!("clip {name}"))
+        .or_insert_with(|| {
+            let (graph, animation) =
+                AnimationGraph::from_clip(clip.clone());
+            CachedPlatformAnimation {
+                graph: graphs.add(graph),
+                animation,
             }
-            PlatformBehavior::Rotate90Y => todo!(),
-            PlatformBehavior::MoveLinear { start, end } => {
-                let start = start.clone();
-                let end = end.clone();
-
-                let platform_target_id =
-                    AnimationTargetId::from_name(
-                        &Name::new("Platform"),
-                    );
+        });
 
-                let mut animation =
-                    AnimationClip::default();
+    let mut player = AnimationPlayer::default();
+    let active = player.play(cached.animation);
+    active.pause().set_speed(speed);
+    if looping {
+        active.repeat();
+    }
 
-                let hold_end_position_curve =
-                    FunctionCurve::new(
-                        Interval::UNIT,
-                        move |_| end,
-                    );
-                let hold_start_position_curve =
-                    FunctionCurve::new(
-                        Interval::UNIT,
-                        move |_| start,
-                    );
-                let translation_curve = EasingCurve::new(
-                    start.clone(),
-                    end.clone(),
-                    EaseFunction::Linear,
+    commands
+        .entity(platform)
+        .remove::<AnimationTarget>()
+        .insert((
+            RigidBody::Kinematic,
+            // the clip poses the platform outright
+            PlatformPose {
+                rest_rotation: Quat::IDENTITY,
+                ..PlatformPose::at_rest(&transform)
+            },
+            PlatformClip::new(
+                cached.animation,
+                period,
+                looping,
+                speed,
+            ),
+            AnimationGraphHandle(cached.graph.clone()),
+            player,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Name::new("ClipPose"),
+                ClipPose,
+                transform,
+                AnimationTarget {
+                    id: target,
+                    player: platform,
+                },
+            ));
+        });
+}
+
+/// Add the curves for one platform or part to
+/// `animation`, and anything they bake in
+/// besides `behavior` to its cache `key`.
+/// Returns whether they repeat, or
+/// `None` after warning if its settings can't
+/// move it.
+fn add_platform_curves(
+    animation: &mut AnimationClip,
+    key: &mut String,
+    target: AnimationTargetId,
+    entity: Entity,
+    transform: &Transform,
+    behavior: Option<&PlatformBehavior>,
+    spin: Option<(&Rotate, &RotationType)>,
+    commands: &mut Commands,
+    children: &Query<&Children>,
+    parents: &Query<&Parent>,
+    transforms: &Query<&Transform>,
+    path_waypoints: &Query<(&PathWaypoint, &Transform)>,
+) -> Option<bool> {
+    if let Some((rotate, rotation_type)) = spin {
+        let Ok(axis) = Dir3::new(rotate.axis) else {
+            warn!(
+                ?entity,
+                axis = ?rotate.axis,
+                "platform rotation axis can't be zero"
+            );
+            return None;
+        };
+        if !add_spin_curve(
+            animation,
+            target,
+            axis,
+            rotation_type,
+        ) {
+            warn!(
+                ?entity,
+                ?rotation_type,
+                "spinning platforms need a speed or steps"
+            );
+            return None;
+        }
+        return Some(true);
+    }
+
+    match behavior? {
+        PlatformBehavior::Rotate90X => {
+            add_rotation_curve(
+                animation,
+                target,
+                Dir3::Z,
+                FRAC_PI_2,
+            );
+        }
+        PlatformBehavior::Rotate90Y => {
+            add_rotation_curve(
+                animation,
+                target,
+                Dir3::Y,
+                FRAC_PI_2,
+            );
+        }
+        PlatformBehavior::RotateAxis { axis, angle } => {
+            let Ok(axis) = Dir3::new(*axis) else {
+                warn!(
+                    ?entity,
+                    ?axis,
+                    "platform rotation axis can't be zero"
+                );
+                return None;
+            };
+            add_rotation_curve(
+                animation, target, axis, *angle,
+            );
+        }
+        PlatformBehavior::MoveLinear { start, end } => {
+            add_move_linear_curve(
+                animation, target, *start, *end,
+            );
+        }
+        PlatformBehavior::Path { waypoints, mode } => {
+            let waypoints = if waypoints.is_empty() {
+                path::child_waypoints(
+                    entity,
+                    transform,
+                    children,
+                    parents,
+                    transforms,
+                    path_waypoints,
                 )
-                .reparametrize_linear(interval(0.0, 4.0).unwrap())
-                .expect("this curve has bounded domain, so this should never fail");
-
-                animation.add_curve_to_target(
-                    platform_target_id,
-                    AnimatableCurve::new(
-                        animated_field!(
-                            Transform::translation
-                        ),
-                        translation_curve
-                            .clone()
-                            .chain(hold_end_position_curve)
-                            .unwrap()
-                            .chain(
-                                translation_curve
-                                    .reverse()
-                                    .unwrap(),
-                            )
-                            .unwrap()
-                            .chain(
-                                hold_start_position_curve,
-                            )
-                            .unwrap(),
-                    ),
+            } else {
+                waypoints.clone()
+            };
+            key.push_str(&format!("{waypoints:?}"));
+
+            let curve = PathCurve::new(&waypoints, *mode);
+            if waypoints.len() < 2 || curve.duration() <= 0.
+            {
+                warn!(
+                    ?entity,
+                    "path platforms need at least two waypoints and some travel time"
                 );
+                return None;
+            }
 
-                let (graph, animation_index) =
-                    AnimationGraph::from_clip(
-                        animations.add(animation),
-                    );
+            animation.add_curve_to_target(
+                target,
+                AnimatableCurve::new(
+                    animated_field!(
+                        PlatformPose::translation
+                    ),
+                    curve,
+                ),
+            );
+            commands.entity(entity).insert(PlatformPath {
+                points: waypoints
+                    .iter()
+                    .map(|waypoint| waypoint.position)
+                    .collect(),
+                mode: *mode,
+            });
+            return Some(*mode != PathMode::Once);
+        }
+        PlatformBehavior::Clip { name, .. } => {
+            warn!(
+                ?entity,
+                name,
+                "only a platform itself can play a clip, not its parts"
+            );
+            return None;
+        }
+    }
+    Some(true)
+}
 
-                // Create the animation player, and set it
-                // to repeat
-                let mut player = AnimationPlayer::default();
+/// Stands in for a platform playing a glTF clip,
+/// which animates this entity's [`Transform`]
+/// instead of the platform's.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct ClipPose;
 
-                // then play now
-                player.play(animation_index).repeat();
+fn apply_clip_poses(
+    proxies: Query<(&Transform, &Parent), With<ClipPose>>,
+    mut poses: Query<&mut PlatformPose>,
+) {
+    for (transform, parent) in &proxies {
+        if let Ok(mut pose) = poses.get_mut(parent.get()) {
+            pose.translation = transform.translation;
+            pose.rotation = transform.rotation;
+            pose.rest_rotation = Quat::IDENTITY;
+        }
+    }
+}
 
-                // if the entity doesn't have an offset
-                // adjustment timer
-                if timers.get(entity).is_ok() {
-                    player.pause_all();
-                }
+/// Parts are moved straight to their pose rather
+/// than through physics, which suits decoration
+/// riding on a platform.
+fn apply_part_poses(
+    mut parts: Query<
+        (&PlatformPose, &mut Transform),
+        Without<Platform>,
+    >,
+) {
+    for (pose, mut transform) in &mut parts {
+        let posed = pose.transform();
+        transform.translation = posed.translation;
+        transform.rotation = posed.rotation;
+    }
+}
 
-                commands.entity(entity).insert((
-                    Processed,
-                    AnimationGraphHandle(graphs.add(graph)),
-                    player,
-                    AnimationTarget {
-                        id: platform_target_id,
-                        player: entity,
-                    },
-                ));
+/// Turn from rest by `angle` radians around
+/// `axis` over four seconds. The clip repeats
+/// from the start, so the angle should be one the
+/// platform looks the same after.
+fn add_rotation_curve(
+    animation: &mut AnimationClip,
+    target: AnimationTargetId,
+    axis: Dir3,
+    angle: f32,
+) {
+    let rotation_curve = EasingCurve::new(
+        Quat::IDENTITY,
+        Quat::from_axis_angle(axis.into(), angle),
+        EaseFunction::ElasticInOut,
+    )
+    .reparametrize_linear(interval(0.0, 4.0).unwrap())
+    .expect("this curve has bounded domain, so this should never fail");
+
+    animation.add_curve_to_target(
+        target,
+        AnimatableCurve::new(
+            animated_field!(PlatformPose::rotation),
+            rotation_curve,
+        ),
+    );
+}
+
+/// One full turn from rest around `axis`, which
+/// the clip repeats. Returns `false`, adding
+/// nothing, if the platform would never move.
+fn add_spin_curve(
+    animation: &mut AnimationClip,
+    target: AnimationTargetId,
+    axis: Dir3,
+    rotation_type: &RotationType,
+) -> bool {
+    let (step_count, turn_secs, pause, direction, ease) =
+        match *rotation_type {
+            RotationType::Stepped { step_count, pause } => {
+                (
+                    step_count,
+                    1.,
+                    pause.max(0.),
+                    1.,
+                    EaseFunction::CubicInOut,
+                )
             }
+            RotationType::Continuous { speed } => (
+                1,
+                TAU / speed.to_radians().abs(),
+                0.,
+                speed.signum(),
+                EaseFunction::Linear,
+            ),
         };
+    let step_secs = turn_secs + pause;
+    let Ok(domain) =
+        interval(0., step_count as f32 * step_secs)
+    else {
+        return false;
+    };
+    if step_count == 0 || !domain.is_bounded() {
+        return false;
     }
+
+    let axis = Vec3::from(axis);
+    let step_angle = TAU / step_count as f32 * direction;
+    let progress = EasingCurve::new(0., 1., ease);
+    let rotation_curve =
+        FunctionCurve::new(domain, move |t| {
+            let step = (t / step_secs).floor();
+            let turned = progress.sample_clamped(
+                (t - step * step_secs) / turn_secs,
+            );
+            Quat::from_axis_angle(
+                axis,
+                (step + turned) * step_angle,
+            )
+        });
+
+    animation.add_curve_to_target(
+        target,
+        AnimatableCurve::new(
+            animated_field!(PlatformPose::rotation),
+            rotation_curve,
+        ),
+    );
+    true
+}
+
+/// Move from `start` to `end` and back, holding
+/// for a second at each end.
+fn add_move_linear_curve(
+    animation: &mut AnimationClip,
+    target: AnimationTargetId,
+    start: Vec3,
+    end: Vec3,
+) {
+    let hold_end_position_curve =
+        FunctionCurve::new(Interval::UNIT, move |_| end);
+    let hold_start_position_curve =
+        FunctionCurve::new(Interval::UNIT, move |_| start);
+    let translation_curve = EasingCurve::new(
+        start,
+        end,
+        EaseFunction::Linear,
+    )
+    .reparametrize_linear(interval(0.0, 4.0).unwrap())
+    .expect("this curve has bounded domain, so this should never fail");
+
+    animation.add_curve_to_target(
+        target,
+        AnimatableCurve::new(
+            animated_field!(PlatformPose::translation),
+            translation_curve
+                .clone()
+                .chain(hold_end_position_curve)
+                .unwrap()
+                .chain(translation_curve.reverse().unwrap())
+                .unwrap()
+                .chain(hold_start_position_curve)
+                .unwrap(),
+        ),
+    );
 }
 
+/// Holds a platform still for the timer's
+/// duration after the level starts, then lets it
+/// run from the [`PlatformClock`]. Kept for
+/// levels authored before
+/// [`PlatformAnimationOffset`]; the timer itself
+/// never ticks.
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct AnimationOffsetTimer(pub Timer);
 
-fn tick_animation_offset_timer(
-    mut commands: Commands,
-    mut timers: Query<(
-        Entity,
-        &mut AnimationPlayer,
-        &mut AnimationOffsetTimer,
-    )>,
+/// Set each platform's velocities so it reaches
+/// its [`PlatformPose`] by the end of this fixed
+/// step. The pose has just been sampled at this
+/// step's time, so every step moves the platform
+/// the same way whatever the frame rate.
+fn drive_kinematic_platforms(
+    mut platforms: Query<
+        (
+            &PlatformPose,
+            Option<&Parent>,
+            &Position,
+            &mut Rotation,
+            &mut LinearVelocity,
+            &mut AngularVelocity,
+        ),
+        With<Platform>,
+    >,
+    global_transforms: Query<&GlobalTransform>,
     time: Res<Time>,
 ) {
-    for (entity, mut player, mut timer) in &mut timers {
-        if timer.0.tick(time.delta()).just_finished() {
-            player.resume_all(); //play(1.into()).repeat();
-            commands
-                .entity(entity)
-                .remove::<AnimationOffsetTimer>();
-        }
+    let delta = time.delta_secs();
+    if delta <= 0. {
+        return;
     }
-}
 
-#[derive(Reflect, Clone)]
-struct RotationProperty;
-
-impl AnimatableProperty for RotationProperty {
-    type Property = Quat;
-    fn get_mut<'a>(
-        &self,
-        entity: &'a mut AnimationEntityMut,
-    ) -> Result<
-        &'a mut Self::Property,
-        AnimationEvaluationError,
-    > {
-        let component = entity
-           .get_mut::<Rotation>()
-           .ok_or(
-                AnimationEvaluationError::ComponentNotPresent(
-                    TypeId::of::<Rotation>()
-               )
-            )?
-            .into_inner();
-        Ok(&mut component.0)
-    }
-
-    fn evaluator_id(&self) -> EvaluatorId {
-        EvaluatorId::Type(TypeId::of::<Self>())
+    for (
+        pose,
+        parent,
+        position,
+        mut rotation,
+        mut linear_velocity,
+        mut angular_velocity,
+    ) in &mut platforms
+    {
+        let local = pose.transform();
+        let target = parent
+            .and_then(|parent| {
+                global_transforms.get(parent.get()).ok()
+            })
+            .map_or(local, |parent| {
+                parent
+                    .mul_transform(local)
+                    .compute_transform()
+            });
+
+        linear_velocity.0 =
+            (target.translation - position.0) / delta;
+
+        let mut turn =
+            target.rotation * rotation.0.inverse();
+        // take the short way round
+        if turn.w < 0. {
+            turn = -turn;
+        }
+        let (_, angle) = turn.to_axis_angle();
+        if angle > PLATFORM_SNAP_ANGLE {
+            rotation.0 = target.rotation;
+            angular_velocity.0 = Vec3::ZERO;
+        } else {
+            angular_velocity.0 =
+                turn.to_scaled_axis() / delta;
+        }
     }
 }
@@ -0,0 +1,207 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use bevy::prelude::*;
+
+use super::{
+    AnimationOffsetTimer, PlatformAnimationOffset,
+};
+use crate::switches::SwitchPlatform;
+
+/// Seconds of platform time in the current level.
+///
+/// Platforms sample their animation at
+/// `(clock + phase_offset) mod period` rather
+/// than playing it, so they stay in step with
+/// each other across pauses and restarts. The
+/// clock only advances with fixed updates and
+/// starts over whenever a level starts.
+#[derive(Resource, Reflect, Debug, Default)]
+#[reflect(Resource)]
+pub struct PlatformClock {
+    pub elapsed: f32,
+}
+
+/// Puts a platform in a named sync group.
+#[derive(Component, Reflect, Debug, Default)]
+#[reflect(Component, Default)]
+pub struct PlatformSync {
+    pub group: String,
+    /// Fraction of a cycle this platform runs
+    /// ahead of the rest of its group.
+    pub phase: f32,
+}
+
+/// Sets the phase of every platform in the group
+/// `name`, as a fraction of each platform's
+/// cycle. Two groups at `0` and `0.5` give
+/// "every other platform" rhythms.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+pub struct PlatformSyncGroup {
+    pub name: String,
+    pub phase: f32,
+}
+
+/// The animation a platform samples from the
+/// [`PlatformClock`].
+#[derive(Component, Debug)]
+pub(super) struct PlatformClip {
+    pub(super) animation: AnimationNodeIndex,
+    pub(super) period: f32,
+    pub(super) repeat: bool,
+    /// Seconds of animation per second of clock.
+    pub(super) speed: f32,
+    /// How far into the animation the platform
+    /// is this fixed step.
+    pub(super) time: f32,
+}
+
+impl PlatformClip {
+    pub(super) fn new(
+        animation: AnimationNodeIndex,
+        period: f32,
+        repeat: bool,
+        speed: f32,
+    ) -> Self {
+        Self {
+            animation,
+            period,
+            repeat,
+            speed,
+            time: 0.,
+        }
+    }
+
+    /// Move the animation to `time`, wrapped
+    /// round or held at the ends as the clip
+    /// repeats or not.
+    fn seek(
+        &mut self,
+        player: &mut AnimationPlayer,
+        time: f32,
+    ) {
+        self.time = if self.repeat {
+            time.rem_euclid(self.period)
+        } else {
+            time.clamp(0., self.period)
+        };
+
+        if let Some(active) =
+            player.animation_mut(self.animation)
+        {
+            active.seek_to(self.time);
+        }
+    }
+}
+
+pub(super) fn reset_platform_clock(
+    mut clock: ResMut<PlatformClock>,
+) {
+    clock.elapsed = 0.;
+}
+
+pub(super) fn tick_platform_clock(
+    mut clock: ResMut<PlatformClock>,
+    time: Res<Time>,
+) {
+    clock.elapsed += time.delta_secs();
+}
+
+/// Platforms on a switch keep their own time, so
+/// they're left for [`advance_switch_platforms`].
+pub(super) fn sync_platforms_to_clock(
+    clock: Res<PlatformClock>,
+    groups: Query<&PlatformSyncGroup>,
+    mut platforms: Query<
+        (
+            &mut PlatformClip,
+            &mut AnimationPlayer,
+            Option<&PlatformSync>,
+            Option<&PlatformAnimationOffset>,
+            Option<&AnimationOffsetTimer>,
+        ),
+        Without<SwitchPlatform>,
+    >,
+) {
+    for (mut clip, mut player, sync, offset, timer) in
+        &mut platforms
+    {
+        if clip.period <= 0. {
+            continue;
+        }
+
+        let group_phase = sync
+            .and_then(|sync| {
+                groups
+                    .iter()
+                    .find(|group| group.name == sync.group)
+            })
+            .map_or(0., |group| group.phase);
+        let phase = sync.map_or(0., |sync| sync.phase)
+            + group_phase;
+        // the platform holds still until its offset
+        // timer's duration has passed
+        let delay = timer.map_or(0., |timer| {
+            timer.0.duration().as_secs_f32()
+        });
+        let elapsed = (clock.elapsed - delay).max(0.);
+        let offset = offset.map_or(0., |offset| offset.0);
+
+        let time = (elapsed + offset) * clip.speed
+            + phase * clip.period;
+        clip.seek(&mut player, time);
+    }
+}
+
+/// Move platforms on a switch along their
+/// animation, in whichever direction the switch
+/// has them going.
+pub(super) fn advance_switch_platforms(
+    mut platforms: Query<(
+        &mut PlatformClip,
+        &mut AnimationPlayer,
+        &SwitchPlatform,
+    )>,
+    time: Res<Time>,
+) {
+    for (mut clip, mut player, switch) in &mut platforms {
+        if clip.period <= 0. {
+            continue;
+        }
+
+        let time = clip.time
+            + switch.direction()
+                * clip.speed
+                * time.delta_secs();
+        clip.seek(&mut player, time);
+    }
+}
+
+/// A dial over each platform, filled as far as
+/// the platform is through its cycle.
+pub(super) fn draw_platform_phases(
+    mut gizmos: Gizmos,
+    platforms: Query<(&PlatformClip, &GlobalTransform)>,
+) {
+    let color = Color::srgb(1., 0.8, 0.2);
+    for (clip, transform) in &platforms {
+        let phase = (clip.time / clip.period).clamp(0., 1.);
+        let center =
+            transform.translation() + Vec3::Y * 1.5;
+
+        gizmos.circle(
+            Isometry3d::new(
+                center,
+                Quat::from_rotation_x(FRAC_PI_2),
+            ),
+            0.4,
+            color.with_alpha(0.3),
+        );
+        gizmos.arc_3d(
+            phase * TAU,
+            0.4,
+            Isometry3d::from_translation(center),
+            color,
+        );
+    }
+}
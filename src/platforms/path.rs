@@ -0,0 +1,307 @@
+use bevy::prelude::*;
+
+/// A stop on a [`PlatformBehavior::Path`].
+///
+/// [`PlatformBehavior::Path`]: super::PlatformBehavior::Path
+#[derive(Debug, Clone, Reflect)]
+#[reflect(Default)]
+pub struct Waypoint {
+    /// Position in the platform's parent space,
+    /// like `MoveLinear`'s `start` and `end`.
+    pub position: Vec3,
+    /// Seconds to travel from this waypoint to
+    /// the next.
+    pub duration: f32,
+    /// Easing for the trip to the next waypoint.
+    pub ease: EaseFunction,
+    /// Seconds to wait here before moving on.
+    pub hold: f32,
+}
+
+impl Default for Waypoint {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            duration: 2.,
+            ease: EaseFunction::CubicInOut,
+            hold: 0.,
+        }
+    }
+}
+
+/// What a path platform does after reaching its
+/// last waypoint.
+#[derive(
+    Debug, Clone, Copy, Reflect, Default, PartialEq,
+)]
+pub enum PathMode {
+    /// Retrace the path back to the first
+    /// waypoint.
+    #[default]
+    PingPong,
+    /// Travel from the last waypoint straight
+    /// back to the first.
+    Loop,
+    /// Stop at the last waypoint.
+    Once,
+}
+
+/// Marks an empty under a path platform as one
+/// of its waypoints, for paths laid out in the
+/// scene instead of listed in the extras. The
+/// empty's position is taken relative to the
+/// platform when the level spawns.
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component, Default)]
+pub struct PathWaypoint {
+    /// Waypoints are visited in ascending order.
+    pub order: u32,
+    pub duration: f32,
+    pub ease: EaseFunction,
+    pub hold: f32,
+}
+
+impl Default for PathWaypoint {
+    fn default() -> Self {
+        let waypoint = Waypoint::default();
+        Self {
+            order: 0,
+            duration: waypoint.duration,
+            ease: waypoint.ease,
+            hold: waypoint.hold,
+        }
+    }
+}
+
+/// The resolved route of a path platform, kept
+/// for debug drawing.
+#[derive(Component, Debug, Clone)]
+pub struct PlatformPath {
+    /// Waypoint positions in the platform's
+    /// parent space.
+    pub points: Vec<Vec3>,
+    pub mode: PathMode,
+}
+
+/// Gather the waypoints laid out as empties under
+/// `platform`, at any depth.
+pub(super) fn child_waypoints(
+    platform: Entity,
+    platform_transform: &Transform,
+    children: &Query<&Children>,
+    parents: &Query<&Parent>,
+    transforms: &Query<&Transform>,
+    waypoints: &Query<(&PathWaypoint, &Transform)>,
+) -> Vec<Waypoint> {
+    let mut found = children
+        .iter_descendants(platform)
+        .filter_map(|entity| {
+            let (waypoint, transform) =
+                waypoints.get(entity).ok()?;
+            // waypoints under intermediate empties
+            // are moved by those empties too
+            let mut position = transform.translation;
+            for ancestor in
+                parents.iter_ancestors(entity).take_while(
+                    |ancestor| *ancestor != platform,
+                )
+            {
+                if let Ok(transform) =
+                    transforms.get(ancestor)
+                {
+                    position =
+                        transform.transform_point(position);
+                }
+            }
+            Some((waypoint, position))
+        })
+        .collect::<Vec<_>>();
+    found.sort_by_key(|(waypoint, _)| waypoint.order);
+
+    found
+        .into_iter()
+        .map(|(waypoint, position)| Waypoint {
+            position: platform_transform
+                .transform_point(position),
+            duration: waypoint.duration,
+            ease: waypoint.ease,
+            hold: waypoint.hold,
+        })
+        .collect()
+}
+
+/// One leg of a [`PathCurve`]. Holds are legs
+/// that start and end in the same place.
+#[derive(Debug, Clone, Reflect)]
+struct PathSegment {
+    start: Vec3,
+    end: Vec3,
+    duration: f32,
+    ease: EaseFunction,
+}
+
+/// Platform translation along a list of
+/// waypoints.
+#[derive(Debug, Clone, Reflect)]
+pub(super) struct PathCurve {
+    segments: Vec<PathSegment>,
+    duration: f32,
+}
+
+impl PathCurve {
+    pub(super) fn new(
+        waypoints: &[Waypoint],
+        mode: PathMode,
+    ) -> Self {
+        let mut segments = vec![];
+        let mut push =
+            |start: Vec3,
+             end: Vec3,
+             duration: f32,
+             ease: EaseFunction| {
+                if duration > 0. {
+                    segments.push(PathSegment {
+                        start,
+                        end,
+                        duration,
+                        ease,
+                    });
+                }
+            };
+        let hold = EaseFunction::Linear;
+
+        if let Some(first) = waypoints.first() {
+            push(
+                first.position,
+                first.position,
+                first.hold,
+                hold,
+            );
+        }
+        for pair in waypoints.windows(2) {
+            let [from, to] = pair else { unreachable!() };
+            push(
+                from.position,
+                to.position,
+                from.duration,
+                from.ease,
+            );
+            push(to.position, to.position, to.hold, hold);
+        }
+
+        match mode {
+            PathMode::PingPong => {
+                // the first waypoint's hold starts the
+                // next repeat
+                for (index, pair) in
+                    waypoints.windows(2).enumerate().rev()
+                {
+                    let [from, to] = pair else {
+                        unreachable!()
+                    };
+                    push(
+                        to.position,
+                        from.position,
+                        from.duration,
+                        from.ease,
+                    );
+                    if index > 0 {
+                        push(
+                            from.position,
+                            from.position,
+                            from.hold,
+                            hold,
+                        );
+                    }
+                }
+            }
+            PathMode::Loop => {
+                if let (Some(first), Some(last)) =
+                    (waypoints.first(), waypoints.last())
+                {
+                    push(
+                        last.position,
+                        first.position,
+                        last.duration,
+                        last.ease,
+                    );
+                }
+            }
+            PathMode::Once => {}
+        }
+
+        let duration = segments
+            .iter()
+            .map(|segment| segment.duration)
+            .sum();
+        Self { segments, duration }
+    }
+
+    pub(super) fn duration(&self) -> f32 {
+        self.duration
+    }
+}
+
+impl Curve<Vec3> for PathCurve {
+    fn domain(&self) -> Interval {
+        interval(0., self.duration)
+            .expect("path durations are never negative")
+    }
+
+    fn sample_unchecked(&self, t: f32) -> Vec3 {
+        let mut start = 0.;
+        for segment in &self.segments {
+            if t <= start + segment.duration {
+                let progress =
+                    (t - start) / segment.duration;
+                return EasingCurve::new(
+                    segment.start,
+                    segment.end,
+                    segment.ease,
+                )
+                .sample_clamped(progress);
+            }
+            start += segment.duration;
+        }
+        self.segments
+            .last()
+            .map_or(Vec3::ZERO, |segment| segment.end)
+    }
+}
+
+pub(super) fn draw_platform_paths(
+    mut gizmos: Gizmos,
+    platforms: Query<(&PlatformPath, Option<&Parent>)>,
+    global_transforms: Query<&GlobalTransform>,
+) {
+    for (path, parent) in &platforms {
+        let to_world = parent
+            .and_then(|parent| {
+                global_transforms.get(parent.get()).ok()
+            })
+            .copied()
+            .unwrap_or_default();
+        let points = path
+            .points
+            .iter()
+            .map(|point| to_world.transform_point(*point))
+            .collect::<Vec<_>>();
+
+        let color = Color::srgb(1., 0.8, 0.2);
+        gizmos.linestrip(points.iter().copied(), color);
+        if path.mode == PathMode::Loop {
+            if let (Some(first), Some(last)) =
+                (points.first(), points.last())
+            {
+                gizmos.line(*last, *first, color);
+            }
+        }
+        for point in &points {
+            gizmos.sphere(
+                Isometry3d::from_translation(*point),
+                0.2,
+                color,
+            );
+        }
+    }
+}
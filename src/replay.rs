@@ -0,0 +1,584 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    AppState, GoalEvent, Player, PlayerIndex,
+    camera::CameraRig,
+    controls::{Action, handle_pantilt, player_input_map},
+    coop::LocalPlayers,
+    level_spawn::{CurrentLevel, LevelState},
+};
+
+const REPLAY_MAGIC: &[u8; 4] = b"BXRP";
+const REPLAY_VERSION: u16 = 2;
+const REPLAY_DIR: &str = "replays";
+
+/// Button actions stored in
+/// [`ReplayFrame::buttons`]. Bit `i` of the mask
+/// is `REPLAY_BUTTONS[i]`, so new actions must
+/// only ever be appended.
+const REPLAY_BUTTONS: [Action; 5] = [
+    Action::Run,
+    Action::Jump,
+    Action::Interact,
+    Action::Dash,
+    Action::Crouch,
+];
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayState>()
+            .add_event::<ReplayCommand>()
+            .add_observer(on_add_player)
+            .add_observer(on_goal_event)
+            .add_systems(
+                Update,
+                (replay_hotkeys, handle_replay_commands)
+                    .chain()
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(
+                FixedPreUpdate,
+                play_frame.never_param_warn(),
+            )
+            .add_systems(
+                FixedUpdate,
+                record_frame
+                    .never_param_warn()
+                    .after(handle_pantilt),
+            );
+    }
+}
+
+/// Run condition for systems that a replay's
+/// stored camera orientation would fight with,
+/// like look input re-clamping pitch to the
+/// current settings.
+pub fn replay_playing(state: Res<ReplayState>) -> bool {
+    matches!(*state, ReplayState::Playing { .. })
+}
+
+#[derive(Event, Debug)]
+pub enum ReplayCommand {
+    /// Restart the current level and record it.
+    StartRecording,
+    /// Stop recording and save the replay.
+    StopRecording,
+    /// Load a replay, restart its level and play
+    /// it back instead of live input.
+    Play(PathBuf),
+    /// Stop playback and return control to the
+    /// player.
+    StopPlayback,
+}
+
+#[derive(Resource, Default)]
+pub enum ReplayState {
+    #[default]
+    Idle,
+    Recording {
+        replay: Replay,
+        /// Set once the player has spawned into
+        /// the restarted level.
+        started: bool,
+    },
+    Playing {
+        replay: Replay,
+        cursor: usize,
+        /// Set once the player has spawned into
+        /// the restarted level.
+        started: bool,
+    },
+}
+
+/// One `FixedUpdate` tick of recorded input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayFrame {
+    /// Pressed state of [`REPLAY_BUTTONS`].
+    pub buttons: u16,
+    pub movement: Vec2,
+    /// Camera orientation after look input was
+    /// applied. Look input depends on the
+    /// player's [`LookSettings`], so the result
+    /// is stored instead of the raw axes.
+    ///
+    /// [`LookSettings`]: crate::settings::LookSettings
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl ReplayFrame {
+    fn capture(
+        action_state: &ActionState<Action>,
+        camera_rig: &CameraRig,
+    ) -> Self {
+        let buttons = REPLAY_BUTTONS
+            .iter()
+            .enumerate()
+            .filter(|(_, action)| {
+                action_state.pressed(action)
+            })
+            .fold(0, |mask, (i, _)| mask | (1 << i));
+
+        Self {
+            buttons,
+            movement: action_state.axis_pair(&Action::Move),
+            yaw: camera_rig.yaw,
+            pitch: camera_rig.pitch,
+        }
+    }
+
+    fn apply(
+        &self,
+        action_state: &mut ActionState<Action>,
+        camera_rig: &mut CameraRig,
+    ) {
+        for (i, action) in REPLAY_BUTTONS.iter().enumerate()
+        {
+            if self.buttons & (1 << i) != 0 {
+                action_state.press(action);
+            } else {
+                action_state.release(action);
+            }
+        }
+        action_state
+            .set_axis_pair(&Action::Move, self.movement);
+
+        camera_rig.yaw = self.yaw;
+        camera_rig.pitch = self.pitch;
+    }
+}
+
+/// A recorded run of a single level.
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    pub level: String,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    /// The default location of the replay for a
+    /// level.
+    pub fn path_for_level(level: &str) -> PathBuf {
+        Path::new(REPLAY_DIR)
+            .join(format!("{level}.replay"))
+    }
+
+    pub fn load(
+        path: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        Self::read_from(&mut fs::File::open(path)?)
+    }
+
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> io::Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::File::create(path)?;
+        self.write_to(&mut file)
+    }
+
+    /// Little-endian layout: magic, version,
+    /// level name, then fixed-size frames.
+    /// Gameplay has no randomness, so the input
+    /// alone reproduces a run.
+    pub fn write_to(
+        &self,
+        writer: &mut impl Write,
+    ) -> io::Result<()> {
+        let level_len = u16::try_from(self.level.len())
+            .map_err(|_| {
+                invalid_data("level name too long")
+            })?;
+        let frame_count = u32::try_from(self.frames.len())
+            .map_err(|_| invalid_data("too many frames"))?;
+
+        writer.write_all(REPLAY_MAGIC)?;
+        writer.write_all(&REPLAY_VERSION.to_le_bytes())?;
+        writer.write_all(&level_len.to_le_bytes())?;
+        writer.write_all(self.level.as_bytes())?;
+        writer.write_all(&frame_count.to_le_bytes())?;
+
+        for frame in &self.frames {
+            writer
+                .write_all(&frame.buttons.to_le_bytes())?;
+            for value in [
+                frame.movement.x,
+                frame.movement.y,
+                frame.yaw,
+                frame.pitch,
+            ] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from(
+        reader: &mut impl Read,
+    ) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != REPLAY_MAGIC {
+            return Err(invalid_data("not a replay file"));
+        }
+
+        let version =
+            u16::from_le_bytes(read_array(reader)?);
+        if version != REPLAY_VERSION {
+            return Err(invalid_data(
+                "unsupported replay version",
+            ));
+        }
+
+        let level_len =
+            u16::from_le_bytes(read_array(reader)?);
+        let mut level = vec![0; level_len as usize];
+        reader.read_exact(&mut level)?;
+        let level =
+            String::from_utf8(level).map_err(|_| {
+                invalid_data("level name is not utf-8")
+            })?;
+
+        let frame_count =
+            u32::from_le_bytes(read_array(reader)?);
+        let frames = (0..frame_count)
+            .map(|_| -> io::Result<ReplayFrame> {
+                let buttons =
+                    u16::from_le_bytes(read_array(reader)?);
+                let mut read_f32 = || {
+                    read_array(reader)
+                        .map(f32::from_le_bytes)
+                };
+                Ok(ReplayFrame {
+                    buttons,
+                    movement: Vec2::new(
+                        read_f32()?,
+                        read_f32()?,
+                    ),
+                    yaw: read_f32()?,
+                    pitch: read_f32()?,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Self { level, frames })
+    }
+}
+
+pub(crate) fn read_array<const N: usize>(
+    reader: &mut impl Read,
+) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn replay_hotkeys(
+    input: Res<ButtonInput<KeyCode>>,
+    state: Res<ReplayState>,
+    current_level: Res<CurrentLevel>,
+    mut commands: EventWriter<ReplayCommand>,
+) {
+    if input.just_pressed(KeyCode::F5) {
+        commands.send(match *state {
+            ReplayState::Recording { .. } => {
+                ReplayCommand::StopRecording
+            }
+            _ => ReplayCommand::StartRecording,
+        });
+    }
+    if input.just_pressed(KeyCode::F6) {
+        commands.send(match *state {
+            ReplayState::Playing { .. } => {
+                ReplayCommand::StopPlayback
+            }
+            _ => ReplayCommand::Play(
+                Replay::path_for_level(&current_level.0),
+            ),
+        });
+    }
+}
+
+fn handle_replay_commands(
+    mut events: EventReader<ReplayCommand>,
+    mut commands: Commands,
+    mut state: ResMut<ReplayState>,
+    mut next_state: ResMut<NextState<LevelState>>,
+    current_level: Res<CurrentLevel>,
+    local_players: Res<LocalPlayers>,
+    player: Option<Single<Entity, With<Player>>>,
+) {
+    for event in events.read() {
+        // replays hold a single player's input
+        if local_players.0 > 1
+            && matches!(
+                event,
+                ReplayCommand::StartRecording
+                    | ReplayCommand::Play(_)
+            )
+        {
+            warn!("replays are single player only");
+            continue;
+        }
+
+        match event {
+            ReplayCommand::StartRecording => {
+                *state = ReplayState::Recording {
+                    replay: Replay {
+                        level: current_level.0.clone(),
+                        frames: vec![],
+                    },
+                    started: false,
+                };
+                next_state.set(LevelState::Loading);
+            }
+            ReplayCommand::StopRecording => {
+                finish_recording(&mut state);
+            }
+            ReplayCommand::Play(path) => {
+                let replay = match Replay::load(path) {
+                    Ok(replay) => replay,
+                    Err(error) => {
+                        error!(
+                            ?error,
+                            ?path,
+                            "failed to load replay"
+                        );
+                        continue;
+                    }
+                };
+                info!(
+                    level = %replay.level,
+                    frames = replay.frames.len(),
+                    "playing replay"
+                );
+
+                commands.insert_resource(CurrentLevel(
+                    replay.level.clone(),
+                ));
+                *state = ReplayState::Playing {
+                    replay,
+                    cursor: 0,
+                    started: false,
+                };
+                next_state.set(LevelState::Loading);
+            }
+            ReplayCommand::StopPlayback => {
+                if let Some(ref player) = player {
+                    commands.entity(**player).insert(
+                        player_input_map(PlayerIndex(0)),
+                    );
+                }
+                *state = ReplayState::Idle;
+            }
+        }
+    }
+}
+
+fn finish_recording(state: &mut ReplayState) {
+    let ReplayState::Recording { replay, .. } = state
+    else {
+        return;
+    };
+
+    let path = Replay::path_for_level(&replay.level);
+    match replay.save(&path) {
+        Ok(()) => info!(
+            ?path,
+            frames = replay.frames.len(),
+            "saved replay"
+        ),
+        Err(error) => {
+            error!(?error, ?path, "failed to save replay")
+        }
+    }
+    *state = ReplayState::Idle;
+}
+
+fn on_add_player(
+    trigger: Trigger<OnAdd, Player>,
+    mut commands: Commands,
+    mut state: ResMut<ReplayState>,
+) {
+    match state.as_mut() {
+        ReplayState::Recording { started, .. } => {
+            *started = true;
+        }
+        ReplayState::Playing { started, .. } => {
+            *started = true;
+            // live input must not leak into the
+            // recorded action states
+            commands
+                .entity(trigger.entity())
+                .remove::<InputMap<Action>>();
+        }
+        ReplayState::Idle => {}
+    }
+}
+
+fn on_goal_event(
+    _trigger: Trigger<GoalEvent>,
+    mut state: ResMut<ReplayState>,
+) {
+    if let ReplayState::Playing { cursor, .. } = *state {
+        info!(ticks = cursor, "replay reached the goal");
+    }
+    finish_recording(&mut state);
+}
+
+fn record_frame(
+    mut state: ResMut<ReplayState>,
+    action_state: Single<
+        &ActionState<Action>,
+        With<Player>,
+    >,
+    camera_rig: Single<&CameraRig>,
+) {
+    let ReplayState::Recording {
+        replay,
+        started: true,
+    } = state.as_mut()
+    else {
+        return;
+    };
+
+    replay.frames.push(ReplayFrame::capture(
+        &action_state,
+        &camera_rig,
+    ));
+}
+
+fn play_frame(
+    mut commands: Commands,
+    mut state: ResMut<ReplayState>,
+    player: Single<
+        (Entity, &Transform, &mut ActionState<Action>),
+        With<Player>,
+    >,
+    mut camera_rig: Single<&mut CameraRig>,
+) {
+    let ReplayState::Playing {
+        replay,
+        cursor,
+        started: true,
+    } = state.as_mut()
+    else {
+        return;
+    };
+    let (entity, transform, mut action_state) =
+        player.into_inner();
+
+    let Some(frame) = replay.frames.get(*cursor) else {
+        info!(
+            ticks = *cursor,
+            translation = ?transform.translation,
+            "replay finished"
+        );
+        commands
+            .entity(entity)
+            .insert(player_input_map(PlayerIndex(0)));
+        *state = ReplayState::Idle;
+        return;
+    };
+
+    frame.apply(&mut action_state, &mut camera_rig);
+    *cursor += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay() -> Replay {
+        Replay {
+            level: "level.002".to_string(),
+            frames: vec![
+                ReplayFrame {
+                    buttons: 0,
+                    movement: Vec2::ZERO,
+                    yaw: 0.,
+                    pitch: 0.,
+                },
+                ReplayFrame {
+                    buttons: 0b10101,
+                    movement: Vec2::new(-0.5, 1.),
+                    yaw: 1.25,
+                    pitch: -0.3,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let replay = replay();
+        let mut bytes = vec![];
+        replay.write_to(&mut bytes).unwrap();
+
+        let read = Replay::read_from(&mut bytes.as_slice())
+            .unwrap();
+        assert_eq!(read.level, replay.level);
+        assert_eq!(read.frames, replay.frames);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut bytes = vec![];
+        replay().write_to(&mut bytes).unwrap();
+        bytes[0] = b'X';
+
+        let error =
+            Replay::read_from(&mut bytes.as_slice())
+                .unwrap_err();
+        assert_eq!(
+            error.kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = vec![];
+        replay().write_to(&mut bytes).unwrap();
+        bytes[4..6].copy_from_slice(
+            &(REPLAY_VERSION + 1).to_le_bytes(),
+        );
+
+        let error =
+            Replay::read_from(&mut bytes.as_slice())
+                .unwrap_err();
+        assert_eq!(
+            error.kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        let mut bytes = vec![];
+        replay().write_to(&mut bytes).unwrap();
+        bytes.pop();
+
+        assert!(
+            Replay::read_from(&mut bytes.as_slice())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::quick::ResourceInspectorPlugin;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::FRAC_PI_4, fs, path::Path};
+
+/// Where player-facing settings are persisted,
+/// relative to the working directory.
+const SETTINGS_PATH: &str = "settings.json";
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = SettingsFile::load();
+
+        app.register_type::<LookSettings>()
+            .register_type::<ResponseCurve>()
+            .register_type::<GhostSettings>()
+            .insert_resource(settings.look)
+            .insert_resource(settings.ghost)
+            .init_resource::<SettingsVisible>()
+            .add_plugins((
+                ResourceInspectorPlugin::<LookSettings>::default()
+                    .run_if(settings_visible),
+                ResourceInspectorPlugin::<GhostSettings>::default()
+                    .run_if(settings_visible),
+            ))
+            .add_systems(Update, toggle_settings)
+            .add_systems(
+                Update,
+                save_settings.run_if(
+                    resource_changed::<LookSettings>
+                        .or(resource_changed::<GhostSettings>)
+                        .and(not(
+                            resource_added::<LookSettings>,
+                        )),
+                ),
+            );
+    }
+}
+
+/// Whether the settings windows are open. Toggled
+/// with F1.
+#[derive(Resource, Default)]
+pub struct SettingsVisible(pub bool);
+
+fn settings_visible(visible: Res<SettingsVisible>) -> bool {
+    visible.0
+}
+
+fn toggle_settings(
+    input: Res<ButtonInput<KeyCode>>,
+    mut visible: ResMut<SettingsVisible>,
+) {
+    if input.just_pressed(KeyCode::F1) {
+        visible.0 = !visible.0;
+    }
+}
+
+/// The on-disk representation of every
+/// persisted settings resource.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct SettingsFile {
+    look: LookSettings,
+    ghost: GhostSettings,
+}
+
+impl SettingsFile {
+    fn load() -> Self {
+        let path = Path::new(SETTINGS_PATH);
+        if !path.exists() {
+            return Self::default();
+        }
+
+        match fs::read_to_string(path).map(|contents| {
+            serde_json::from_str::<SettingsFile>(&contents)
+        }) {
+            Ok(Ok(settings)) => settings,
+            Ok(Err(error)) => {
+                warn!(
+                    ?error,
+                    "failed to parse {SETTINGS_PATH}, using defaults"
+                );
+                Self::default()
+            }
+            Err(error) => {
+                warn!(
+                    ?error,
+                    "failed to read {SETTINGS_PATH}, using defaults"
+                );
+                Self::default()
+            }
+        }
+    }
+}
+
+fn save_settings(
+    look: Res<LookSettings>,
+    ghost: Res<GhostSettings>,
+) {
+    let settings = SettingsFile {
+        look: look.clone(),
+        ghost: ghost.clone(),
+    };
+
+    let contents =
+        match serde_json::to_string_pretty(&settings) {
+            Ok(contents) => contents,
+            Err(error) => {
+                error!(
+                    ?error,
+                    "failed to serialize settings"
+                );
+                return;
+            }
+        };
+
+    if let Err(error) = fs::write(SETTINGS_PATH, contents) {
+        error!(?error, "failed to write {SETTINGS_PATH}");
+    }
+}
+
+/// Camera look sensitivity, inversion and pitch
+/// limits.
+#[derive(
+    Resource, Reflect, Serialize, Deserialize, Clone, Debug,
+)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct LookSettings {
+    /// Radians of rotation per pixel of mouse
+    /// movement.
+    pub mouse_sensitivity: f32,
+    /// Radians per second of rotation at full
+    /// stick deflection.
+    pub gamepad_sensitivity: f32,
+    /// Response curve applied to the look stick
+    /// before sensitivity.
+    pub gamepad_response: ResponseCurve,
+    pub invert_x: bool,
+    pub invert_y: bool,
+    /// Lowest allowed [`CameraRig::pitch`]
+    /// (radians).
+    ///
+    /// [`CameraRig::pitch`]: crate::camera::CameraRig::pitch
+    pub min_pitch: f32,
+    /// Highest allowed [`CameraRig::pitch`]
+    /// (radians).
+    ///
+    /// [`CameraRig::pitch`]: crate::camera::CameraRig::pitch
+    pub max_pitch: f32,
+}
+
+impl Default for LookSettings {
+    fn default() -> Self {
+        Self {
+            mouse_sensitivity: 1. / 90.,
+            gamepad_sensitivity: 2.,
+            gamepad_response: ResponseCurve::Quadratic,
+            invert_x: false,
+            invert_y: false,
+            min_pitch: -1.4,
+            // the camera is kept out of the ground,
+            // so the player can look up
+            max_pitch: FRAC_PI_4,
+        }
+    }
+}
+
+impl LookSettings {
+    /// Per-axis multiplier applying `invert_x`
+    /// and `invert_y`.
+    pub fn inversion(&self) -> Vec2 {
+        Vec2::new(
+            if self.invert_x { -1. } else { 1. },
+            if self.invert_y { -1. } else { 1. },
+        )
+    }
+}
+
+/// How stick deflection maps to look speed.
+#[derive(
+    Reflect, Serialize, Deserialize, Clone, Copy, Debug,
+)]
+pub enum ResponseCurve {
+    Linear,
+    Quadratic,
+    Cubic,
+    /// Raise the deflection to an arbitrary
+    /// power.
+    Power(f32),
+}
+
+impl ResponseCurve {
+    /// Reshape the magnitude of a stick input,
+    /// preserving its direction.
+    pub fn apply(&self, input: Vec2) -> Vec2 {
+        let magnitude = input.length();
+        if magnitude == 0. {
+            return Vec2::ZERO;
+        }
+
+        let shaped = match self {
+            ResponseCurve::Linear => magnitude,
+            ResponseCurve::Quadratic => magnitude.powi(2),
+            ResponseCurve::Cubic => magnitude.powi(3),
+            ResponseCurve::Power(exponent) => {
+                magnitude.powf(*exponent)
+            }
+        };
+
+        input / magnitude * shaped
+    }
+}
+
+/// Whether the best run for the current level is
+/// shown as a ghost.
+#[derive(
+    Resource, Reflect, Serialize, Deserialize, Clone, Debug,
+)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct GhostSettings {
+    pub enabled: bool,
+}
+
+impl Default for GhostSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
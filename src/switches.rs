@@ -0,0 +1,312 @@
+use avian3d::prelude::{
+    Collider, ColliderDisabled, CollidingEntities,
+    ComputedMass, Sensor,
+};
+use bevy::{prelude::*, utils::HashSet};
+use leafwing_input_manager::prelude::*;
+
+use crate::{Holding, Player, Target, controls::Action};
+
+pub struct SwitchesPlugin;
+
+impl Plugin for SwitchesPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<PressurePlate>()
+            .register_type::<ToggleSwitch>()
+            .register_type::<TimerSwitch>()
+            .register_type::<SwitchDoor>()
+            .register_type::<SwitchPlatform>()
+            .register_type::<SwitchResponse>()
+            .init_resource::<ActiveChannels>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    use_toggle_switches,
+                    tick_switches,
+                    update_active_channels,
+                    (
+                        update_switch_doors,
+                        update_switch_platforms,
+                    ),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Channels with at least one switch turned on
+/// this fixed step.
+///
+/// Switches send on a channel, and doors and
+/// platforms listen on one. A receiver with an
+/// empty channel listens on its own [`Name`], so
+/// a switch can be wired either to a shared tag
+/// or straight to one entity by name.
+#[derive(Resource, Default, Debug, Deref)]
+pub struct ActiveChannels(HashSet<String>);
+
+/// The channel a receiver listens on.
+fn listens_on<'a>(
+    channel: &'a str,
+    name: Option<&'a Name>,
+) -> &'a str {
+    match (channel, name) {
+        ("", Some(name)) => name.as_str(),
+        _ => channel,
+    }
+}
+
+/// Pressed while a player or a [`Target`] rests
+/// on it. The plate also needs a collider.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+#[require(Sensor, CollidingEntities)]
+pub struct PressurePlate {
+    pub channel: String,
+    /// Combined mass the things on the plate need
+    /// before it counts as pressed. `0` accepts
+    /// anything.
+    pub min_mass: f32,
+}
+
+/// Flipped on and off by a player pressing
+/// [`Action::Interact`] inside it. The switch
+/// also needs a collider.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+#[require(Sensor, CollidingEntities)]
+pub struct ToggleSwitch {
+    pub channel: String,
+    pub on: bool,
+    /// Seconds before the switch turns itself
+    /// back off. `0` leaves it on.
+    pub reset_after: f32,
+    #[reflect(ignore)]
+    elapsed: f32,
+}
+
+/// Turns itself on for `on_secs`, then off for
+/// `off_secs`, forever.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+pub struct TimerSwitch {
+    pub channel: String,
+    pub on_secs: f32,
+    pub off_secs: f32,
+    #[reflect(ignore)]
+    elapsed: f32,
+}
+
+/// Level geometry that gets out of the way while
+/// its channel is active, so one player can hold
+/// a path open for another. Colliders anywhere
+/// under the door are switched off with it.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+pub struct SwitchDoor {
+    pub channel: String,
+}
+
+/// A platform whose animation is controlled by a
+/// channel instead of looping from spawn.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+pub struct SwitchPlatform {
+    pub channel: String,
+    pub response: SwitchResponse,
+    /// `1` to run the animation forwards, `-1`
+    /// backwards and `0` to hold it.
+    #[reflect(ignore)]
+    direction: f32,
+}
+
+impl SwitchPlatform {
+    /// Which way the platform's animation runs
+    /// this fixed step.
+    pub fn direction(&self) -> f32 {
+        self.direction
+    }
+}
+
+/// What a [`SwitchPlatform`] does while its
+/// channel is active.
+#[derive(Debug, Clone, Copy, Reflect, Default)]
+pub enum SwitchResponse {
+    /// Move only while active.
+    #[default]
+    Play,
+    /// Move except while active.
+    Pause,
+    /// Move backwards while active, forwards
+    /// otherwise.
+    Reverse,
+}
+
+/// Toggle switches take the interact press before
+/// picking up or throwing does, but a player with
+/// something in hand throws it instead.
+pub fn use_toggle_switches(
+    mut switches: Query<(
+        &mut ToggleSwitch,
+        &CollidingEntities,
+    )>,
+    mut players: Query<
+        (Entity, &Holding, &mut ActionState<Action>),
+        With<Player>,
+    >,
+) {
+    for (player, holding, mut action_state) in &mut players
+    {
+        if !action_state.just_pressed(&Action::Interact)
+            || holding.is_some()
+        {
+            continue;
+        }
+        let Some((mut switch, _)) =
+            switches.iter_mut().find(|(_, colliding)| {
+                colliding.contains(&player)
+            })
+        else {
+            continue;
+        };
+
+        switch.on = !switch.on;
+        switch.elapsed = 0.;
+        action_state.consume(&Action::Interact);
+    }
+}
+
+fn tick_switches(
+    mut toggles: Query<&mut ToggleSwitch>,
+    mut timers: Query<&mut TimerSwitch>,
+    time: Res<Time>,
+) {
+    for mut switch in &mut toggles {
+        if !switch.on || switch.reset_after <= 0. {
+            continue;
+        }
+        switch.elapsed += time.delta_secs();
+        if switch.elapsed >= switch.reset_after {
+            switch.on = false;
+        }
+    }
+
+    for mut timer in &mut timers {
+        let period = timer.on_secs + timer.off_secs;
+        if period > 0. {
+            timer.elapsed = (timer.elapsed
+                + time.delta_secs())
+                % period;
+        }
+    }
+}
+
+fn update_active_channels(
+    mut channels: ResMut<ActiveChannels>,
+    plates: Query<(&PressurePlate, &CollidingEntities)>,
+    toggles: Query<&ToggleSwitch>,
+    timers: Query<&TimerSwitch>,
+    pressers: Query<
+        Option<&ComputedMass>,
+        Or<(With<Player>, With<Target>)>,
+    >,
+) {
+    channels.0.clear();
+
+    for (plate, colliding) in &plates {
+        let mut pressed = false;
+        let mut mass = 0.;
+        for presser in colliding
+            .iter()
+            .filter_map(|entity| pressers.get(*entity).ok())
+        {
+            pressed = true;
+            mass += presser.map_or(0., |mass| mass.value());
+        }
+        if pressed && mass >= plate.min_mass {
+            channels.0.insert(plate.channel.clone());
+        }
+    }
+
+    for switch in &toggles {
+        if switch.on {
+            channels.0.insert(switch.channel.clone());
+        }
+    }
+
+    for timer in &timers {
+        if timer.elapsed < timer.on_secs {
+            channels.0.insert(timer.channel.clone());
+        }
+    }
+}
+
+fn update_switch_doors(
+    mut commands: Commands,
+    channels: Res<ActiveChannels>,
+    mut doors: Query<(
+        Entity,
+        &SwitchDoor,
+        Option<&Name>,
+        &mut Visibility,
+        Has<ColliderDisabled>,
+    )>,
+    children: Query<&Children>,
+    colliders: Query<(), With<Collider>>,
+) {
+    for (entity, door, name, mut visibility, open) in
+        &mut doors
+    {
+        let should_open = channels
+            .contains(listens_on(&door.channel, name));
+        if should_open == open {
+            continue;
+        }
+
+        // the door is marked even without a
+        // collider of its own, since the marker
+        // is how it knows it's open
+        let parts = children
+            .iter_descendants(entity)
+            .filter(|part| colliders.contains(*part));
+        for part in std::iter::once(entity).chain(parts) {
+            if should_open {
+                commands
+                    .entity(part)
+                    .insert(ColliderDisabled);
+            } else {
+                commands
+                    .entity(part)
+                    .remove::<ColliderDisabled>();
+            }
+        }
+        *visibility = if should_open {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+    }
+}
+
+/// Set which way each [`SwitchPlatform`] runs.
+/// The platform moves itself along its
+/// animation after this.
+pub(crate) fn update_switch_platforms(
+    channels: Res<ActiveChannels>,
+    mut platforms: Query<(
+        &mut SwitchPlatform,
+        Option<&Name>,
+    )>,
+) {
+    for (mut platform, name) in &mut platforms {
+        let active = channels
+            .contains(listens_on(&platform.channel, name));
+        platform.direction =
+            match (platform.response, active) {
+                (SwitchResponse::Play, false)
+                | (SwitchResponse::Pause, true) => 0.,
+                (SwitchResponse::Reverse, true) => -1.,
+                _ => 1.,
+            };
+    }
+}